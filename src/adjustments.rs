@@ -0,0 +1,130 @@
+use magick_rust::{MagickError, MagickWand};
+
+use crate::functions::map_pixels;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Tone and color adjustments applied after resizing. The default values do not change the image.
+///
+/// The adjustments are applied in this order: `auto_white_balance`, `auto_level`, `gamma`, `brightness` and `contrast`, `saturation` and `hue`.
+pub struct Adjustments {
+    /// From -100 to 100, the brightness change. `0` means no change.
+    pub brightness:         f64,
+    /// From -100 to 100, the contrast change. `0` means no change.
+    pub contrast:           f64,
+    /// The saturation (in percent). `100` means no change and `0` means grayscale.
+    pub saturation:         f64,
+    /// The hue rotation angle in degrees.
+    pub hue:                f64,
+    /// The gamma correction, which must be greater than 0. `1` means no change.
+    pub gamma:              f64,
+    /// Stretch the range of the colors to the full range (normalize).
+    pub auto_level:         bool,
+    /// Remove the color cast by assuming that the average color of the image is gray.
+    pub auto_white_balance: bool,
+}
+
+impl Adjustments {
+    /// Create an `Adjustments` instance with default values.
+    /// ```rust,ignore
+    /// Adjustments {
+    ///     brightness: 0f64,
+    ///     contrast: 0f64,
+    ///     saturation: 100f64,
+    ///     hue: 0f64,
+    ///     gamma: 1f64,
+    ///     auto_level: false,
+    ///     auto_white_balance: false,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> Adjustments {
+        Adjustments {
+            brightness:         0f64,
+            contrast:           0f64,
+            saturation:         100f64,
+            hue:                0f64,
+            gamma:              1f64,
+            auto_level:         false,
+            auto_white_balance: false,
+        }
+    }
+
+    /// Apply the adjustments to an image.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        if self.auto_white_balance {
+            white_balance(mw)?;
+        }
+
+        if self.auto_level {
+            mw.auto_level()?;
+        }
+
+        if self.gamma != 1f64 {
+            if self.gamma <= 0f64 || !self.gamma.is_finite() {
+                return Err("The gamma must be greater than 0.".into());
+            }
+
+            mw.level_image(0f64, self.gamma, 1f64)?;
+        }
+
+        if self.brightness != 0f64 || self.contrast != 0f64 {
+            mw.brightness_contrast_image(
+                self.brightness.clamp(-100f64, 100f64),
+                self.contrast.clamp(-100f64, 100f64),
+            )?;
+        }
+
+        let hue = (self.hue + 180f64).rem_euclid(360f64) - 180f64;
+
+        if self.saturation != 100f64 || hue != 0f64 {
+            // for ImageMagick, the hue is 100 when it is not rotated, and 0 or 200 when it is rotated by 180 degrees
+            mw.modulate_image(100f64, self.saturation.max(0f64), 100f64 + hue / 1.8)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Adjustments {
+    #[inline]
+    fn default() -> Self {
+        Adjustments::new()
+    }
+}
+
+/// Scale the RGB channels so that the average color of the visible pixels becomes gray (the gray world assumption).
+fn white_balance(mw: &mut MagickWand) -> Result<(), MagickError> {
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let alphas = match mw.export_image_pixels_double(0, 0, width, height, "A") {
+        Some(alphas) => alphas,
+        None => return Err("Cannot export the pixels of the image.".into()),
+    };
+
+    map_pixels(mw, |pixels, _, _| {
+        let mut sums = [0f64; 3];
+
+        for (pixel, alpha) in pixels.chunks_exact(3).zip(&alphas) {
+            if *alpha > 0f64 {
+                for (sum, sample) in sums.iter_mut().zip(pixel) {
+                    *sum += sample;
+                }
+            }
+        }
+
+        // a channel without any value cannot be scaled
+        if sums.contains(&0f64) {
+            return;
+        }
+
+        let gray = sums.iter().sum::<f64>() / 3f64;
+        let factors = sums.map(|sum| gray / sum);
+
+        for pixel in pixels.chunks_exact_mut(3) {
+            for (sample, factor) in pixel.iter_mut().zip(factors) {
+                *sample = (*sample * factor).min(1f64);
+            }
+        }
+    })
+}
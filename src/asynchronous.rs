@@ -0,0 +1,381 @@
+use std::{
+    cell::RefCell,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use magick_rust::{MagickError, MagickWand};
+use once_cell::sync::OnceCell;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::Semaphore,
+    task,
+};
+
+use crate::{
+    identify_ping, identify_read, to_cur, to_icns, to_ico, watermark::WatermarkCache, AsImageInput,
+    BMPConfig, GIFConfig, GrayRawConfig, ICNSConfig, ICOConfig, ImageIdentify, ImageResource,
+    JPGConfig, OutputConfig, PGMConfig, PNGConfig, TIFFConfig, WEBPConfig,
+};
+
+/// The error message used when an async operation is cancelled.
+pub const CANCELLED: &str = "The operation is cancelled.";
+
+/// Check whether an error is caused by cancelling an async operation.
+#[inline]
+pub fn is_cancelled(error: &MagickError) -> bool {
+    error.to_string() == CANCELLED
+}
+
+/// The cancellation state of a job running on a `BlockingPool`. A job is cancelled when the future waiting for it is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    /// Whether the job has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Return the `CANCELLED` error if the job has been cancelled. Jobs call this at their stage boundaries.
+    #[inline]
+    pub fn check(&self) -> Result<(), MagickError> {
+        if self.is_cancelled() {
+            Err(CANCELLED.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Cancel a job if the future waiting for it is dropped before it finishes.
+struct CancelOnDrop {
+    cancellation: Cancellation,
+    finished:     bool,
+}
+
+impl Drop for CancelOnDrop {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.finished {
+            self.cancellation.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+thread_local! {
+    /// The cancellation of the job running on the current blocking thread.
+    static CURRENT_JOB: RefCell<Option<Cancellation>> = const { RefCell::new(None) };
+}
+
+/// Make a cancellation visible to the stage boundaries of the conversion functions while a job is running on the current thread.
+struct CurrentJob;
+
+impl CurrentJob {
+    #[inline]
+    fn enter(cancellation: &Cancellation) -> CurrentJob {
+        CURRENT_JOB.with(|current| *current.borrow_mut() = Some(cancellation.clone()));
+
+        CurrentJob
+    }
+}
+
+impl Drop for CurrentJob {
+    #[inline]
+    fn drop(&mut self) {
+        CURRENT_JOB.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+/// Return the `CANCELLED` error if the job running on the current thread has been cancelled.
+#[inline]
+pub(crate) fn check_current_job() -> Result<(), MagickError> {
+    CURRENT_JOB.with(|current| match current.borrow().as_ref() {
+        Some(cancellation) => cancellation.check(),
+        None => Ok(()),
+    })
+}
+
+static GLOBAL_POOL: OnceCell<BlockingPool> = OnceCell::new();
+
+/// A pool which runs the blocking ImageMagick work on the tokio blocking threads, with a bounded number of concurrent jobs.
+#[derive(Debug, Clone)]
+pub struct BlockingPool {
+    semaphore: Arc<Semaphore>,
+}
+
+impl BlockingPool {
+    /// Create a pool which runs at most `concurrency` jobs at the same time.
+    #[inline]
+    pub fn new(concurrency: usize) -> BlockingPool {
+        BlockingPool {
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1)))
+        }
+    }
+
+    /// Set the concurrency of the global pool used by the `*_async` functions. It returns `false` if the global pool has already been created.
+    #[inline]
+    pub fn init_global(concurrency: usize) -> bool {
+        GLOBAL_POOL.set(BlockingPool::new(concurrency)).is_ok()
+    }
+
+    /// Get the global pool. Its concurrency is the available parallelism by default.
+    #[inline]
+    pub fn global() -> &'static BlockingPool {
+        GLOBAL_POOL.get_or_init(|| {
+            BlockingPool::new(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+        })
+    }
+
+    /// Run a blocking job. If the returned future is dropped, the job is notified through its `Cancellation` and should stop at its next stage boundary. The conversion functions of this crate called by the job also check it between resizing, post-processing and encoding.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&Cancellation) -> T + Send + 'static,
+    ) -> Result<T, MagickError> {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| "The blocking pool is closed.")?;
+
+        let mut guard = CancelOnDrop {
+            cancellation: Cancellation::default(), finished: false
+        };
+
+        let cancellation = guard.cancellation.clone();
+
+        let result = task::spawn_blocking(move || {
+            let _permit = permit;
+            let _current_job = CurrentJob::enter(&cancellation);
+
+            job(&cancellation)
+        })
+        .await
+        .map_err(|_| MagickError::from("The blocking job panicked."));
+
+        guard.finished = true;
+
+        result
+    }
+}
+
+/// The output of an async conversion.
+pub enum AsyncOutput<'a> {
+    /// An image resource. It is moved into the blocking job and put back when the job finishes, so it is left empty if the future is dropped before that.
+    Resource(&'a mut ImageResource),
+    /// An async writer. The whole encoded image is buffered in memory and written to it after the conversion.
+    Writer(&'a mut (dyn AsyncWrite + Unpin + Send)),
+}
+
+impl<'a> From<&'a mut ImageResource> for AsyncOutput<'a> {
+    #[inline]
+    fn from(resource: &'a mut ImageResource) -> Self {
+        AsyncOutput::Resource(resource)
+    }
+}
+
+impl<'a> AsyncOutput<'a> {
+    /// Create an `AsyncOutput` instance from an async writer. The whole encoded image is buffered in memory before it is written.
+    #[inline]
+    pub fn writer<W: AsyncWrite + Unpin + Send>(writer: &'a mut W) -> Self {
+        AsyncOutput::Writer(writer)
+    }
+}
+
+async fn convert_async(
+    output: AsyncOutput<'_>,
+    job: impl FnOnce(&mut ImageResource, &Cancellation) -> Result<(), MagickError> + Send + 'static,
+) -> Result<(), MagickError> {
+    match output {
+        AsyncOutput::Resource(resource) => {
+            let mut temp = mem::replace(resource, ImageResource::Data(Vec::new()));
+
+            let (temp, result) = BlockingPool::global()
+                .run(move |cancellation| {
+                    let result = cancellation.check().and_then(|_| job(&mut temp, cancellation));
+
+                    (temp, result)
+                })
+                .await?;
+
+            *resource = temp;
+
+            result
+        },
+        AsyncOutput::Writer(writer) => {
+            let data = BlockingPool::global()
+                .run(move |cancellation| {
+                    cancellation.check()?;
+
+                    let mut temp = ImageResource::with_capacity(0);
+
+                    job(&mut temp, cancellation)?;
+
+                    Ok::<_, MagickError>(temp.into_vec().unwrap_or_default())
+                })
+                .await??;
+
+            writer.write_all(&data).await.map_err(|_| "Cannot write the image data.")?;
+            writer.flush().await.map_err(|_| "Cannot write the image data.")?;
+
+            Ok(())
+        },
+    }
+}
+
+macro_rules! staged_async {
+    ($(#[$attr:meta])* $name:ident, $config:ty, $variant:ident) => {
+        $(#[$attr])*
+        pub async fn $name<I: AsImageInput + Send + 'static>(
+            output: impl Into<AsyncOutput<'_>>,
+            input: I,
+            config: $config,
+        ) -> Result<(), MagickError> {
+            convert_async(output.into(), move |output, cancellation| {
+                let config = OutputConfig::$variant(&config);
+
+                let (mw, vector) = config.fetch(&input)?;
+
+                cancellation.check()?;
+
+                config.encode(output, mw, vector, &mut WatermarkCache::new())
+            })
+            .await
+        }
+    };
+}
+
+staged_async!(
+    /// Convert an image to a BMP image on the blocking pool.
+    to_bmp_async,
+    BMPConfig,
+    BMP
+);
+staged_async!(
+    /// Convert an image to a GIF image on the blocking pool.
+    to_gif_async,
+    GIFConfig,
+    GIF
+);
+staged_async!(
+    /// Convert an image to a GrayRaw image on the blocking pool.
+    to_gray_raw_async,
+    GrayRawConfig,
+    GrayRaw
+);
+staged_async!(
+    /// Convert an image to a JPEG image on the blocking pool.
+    to_jpg_async,
+    JPGConfig,
+    JPG
+);
+staged_async!(
+    /// Convert an image to a PGM image on the blocking pool.
+    to_pgm_async,
+    PGMConfig,
+    PGM
+);
+staged_async!(
+    /// Convert an image to a PNG image on the blocking pool.
+    to_png_async,
+    PNGConfig,
+    PNG
+);
+staged_async!(
+    /// Convert an image to a TIFF image on the blocking pool.
+    to_tiff_async,
+    TIFFConfig,
+    TIFF
+);
+staged_async!(
+    /// Convert an image to a WebP image on the blocking pool.
+    to_webp_async,
+    WEBPConfig,
+    WEBP
+);
+
+/// Convert an image to an ICO image on the blocking pool.
+pub async fn to_ico_async<I: AsImageInput + Send + 'static>(
+    output: impl Into<AsyncOutput<'_>>,
+    input: I,
+    config: ICOConfig,
+) -> Result<(), MagickError> {
+    convert_async(output.into(), move |output, cancellation| {
+        to_ico(output, &input, &config)?;
+
+        cancellation.check()
+    })
+    .await
+}
+
+/// Convert an image to a CUR image on the blocking pool.
+pub async fn to_cur_async<I: AsImageInput + Send + 'static>(
+    output: impl Into<AsyncOutput<'_>>,
+    input: I,
+    config: ICOConfig,
+) -> Result<(), MagickError> {
+    convert_async(output.into(), move |output, cancellation| {
+        to_cur(output, &input, &config)?;
+
+        cancellation.check()
+    })
+    .await
+}
+
+/// Convert an image to an ICNS image on the blocking pool.
+pub async fn to_icns_async<I: AsImageInput + Send + 'static>(
+    output: impl Into<AsyncOutput<'_>>,
+    input: I,
+    config: ICNSConfig,
+) -> Result<(), MagickError> {
+    convert_async(output.into(), move |output, cancellation| {
+        to_icns(output, &input, &config)?;
+
+        cancellation.check()
+    })
+    .await
+}
+
+/// Ping and identify an image on the blocking pool.
+pub async fn identify_ping_async<I: AsImageInput + Send + 'static>(
+    input: I,
+) -> Result<ImageIdentify, MagickError> {
+    BlockingPool::global()
+        .run(move |cancellation| {
+            cancellation.check()?;
+
+            identify_ping(&input)
+        })
+        .await?
+}
+
+/// Read and identify an image on the blocking pool. It can read an image as `MagickWand` instances.
+pub async fn identify_read_async<I: AsImageInput + Send + 'static>(
+    output: &mut Option<MagickWand>,
+    input: I,
+) -> Result<ImageIdentify, MagickError> {
+    let (identify, mw) = BlockingPool::global()
+        .run(move |cancellation| {
+            cancellation.check()?;
+
+            let mut output = None;
+
+            let identify = identify_read(&mut output, &input)?;
+
+            // `ImageResource` is used to send the `MagickWand` instance back
+            Ok::<_, MagickError>((identify, output.map(ImageResource::MagickWand)))
+        })
+        .await??;
+
+    *output = mw.and_then(|mw| mw.into_magick_wand());
+
+    Ok(identify)
+}
@@ -0,0 +1,248 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use magick_rust::{MagickError, ResourceType};
+
+use crate::{
+    resource_limits::ResourceLimitGuard, runtime::begin_operation, BMPConfig, GIFConfig,
+    GrayRawConfig, ImageResource, JPGConfig, OutputConfig, PGMConfig, PNGConfig, TIFFConfig,
+    WEBPConfig,
+};
+
+/// The number of the running batches, and the thread resource limit of ImageMagick set by the first of them.
+static THREAD_LIMIT: Mutex<(usize, Option<ResourceLimitGuard>)> = Mutex::new((0, None));
+
+/// The thread resource limit of ImageMagick shared by the running batches. It is set when the first batch starts, and restored when the last batch finishes, so batches running at the same time do not have to wait for each other.
+struct SharedThreadLimit {
+    _private: (),
+}
+
+impl SharedThreadLimit {
+    fn acquire(magick_threads: usize) -> Result<SharedThreadLimit, MagickError> {
+        let mut limit = THREAD_LIMIT.lock().unwrap_or_else(|err| err.into_inner());
+
+        if limit.0 == 0 {
+            limit.1 =
+                Some(ResourceLimitGuard::set(&[(ResourceType::Thread, magick_threads as u64)])?);
+        }
+
+        limit.0 += 1;
+
+        Ok(SharedThreadLimit {
+            _private: ()
+        })
+    }
+}
+
+impl Drop for SharedThreadLimit {
+    #[inline]
+    fn drop(&mut self) {
+        let mut limit = THREAD_LIMIT.lock().unwrap_or_else(|err| err.into_inner());
+
+        limit.0 -= 1;
+
+        if limit.0 == 0 {
+            // restore the previous limit
+            limit.1 = None;
+        }
+    }
+}
+
+/// The output config of a batch job. A job owns its config, so that it can be moved to a worker thread.
+#[derive(Debug)]
+pub enum BatchConfig {
+    BMP(BMPConfig),
+    GIF(GIFConfig),
+    GrayRaw(GrayRawConfig),
+    JPG(JPGConfig),
+    PGM(PGMConfig),
+    PNG(PNGConfig),
+    TIFF(TIFFConfig),
+    WEBP(WEBPConfig),
+}
+
+impl BatchConfig {
+    #[inline]
+    fn as_output_config(&self) -> OutputConfig<'_> {
+        match self {
+            BatchConfig::BMP(config) => OutputConfig::BMP(config),
+            BatchConfig::GIF(config) => OutputConfig::GIF(config),
+            BatchConfig::GrayRaw(config) => OutputConfig::GrayRaw(config),
+            BatchConfig::JPG(config) => OutputConfig::JPG(config),
+            BatchConfig::PGM(config) => OutputConfig::PGM(config),
+            BatchConfig::PNG(config) => OutputConfig::PNG(config),
+            BatchConfig::TIFF(config) => OutputConfig::TIFF(config),
+            BatchConfig::WEBP(config) => OutputConfig::WEBP(config),
+        }
+    }
+}
+
+/// A conversion job of a batch.
+#[derive(Debug)]
+pub struct BatchJob {
+    pub input:  ImageResource,
+    pub output: ImageResource,
+    pub config: BatchConfig,
+}
+
+impl BatchJob {
+    /// Create a `BatchJob` instance.
+    #[inline]
+    pub fn new(input: ImageResource, output: ImageResource, config: BatchConfig) -> Self {
+        BatchJob {
+            input,
+            output,
+            config,
+        }
+    }
+}
+
+/// The result of a conversion job of a batch.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// The output resource of the job.
+    pub output:  ImageResource,
+    /// The result of the conversion.
+    pub result:  Result<(), MagickError>,
+    /// The time spent on the job.
+    pub elapsed: Duration,
+}
+
+/// The aggregate statistics of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchStats {
+    /// The number of the succeeded jobs.
+    pub succeeded: usize,
+    /// The number of the failed jobs.
+    pub failed:    usize,
+    /// The wall-clock time spent on the whole batch.
+    pub elapsed:   Duration,
+    /// The sum of the time spent on each job.
+    pub busy:      Duration,
+}
+
+/// The report of a batch.
+#[derive(Debug)]
+pub struct BatchReport {
+    /// The results of the jobs, in the same order as the jobs.
+    pub results: Vec<BatchResult>,
+    pub stats:   BatchStats,
+}
+
+/// Run many conversion jobs on a pool of worker threads.
+///
+/// ImageMagick also runs its own threads for each operation, so the thread resource limit of ImageMagick is lowered to `magick_threads` while a batch is running, to avoid oversubscribing the CPU. The limit is shared by the whole process, so other ImageMagick calls made during the batch are capped as well. Batches running at the same time do not wait for each other, and they share the limit set by the first of them. The previous limit is restored when the last of them finishes.
+#[derive(Debug, Clone, Copy)]
+pub struct Batch {
+    /// The number of worker threads. `0` means the available parallelism.
+    pub workers:        usize,
+    /// The number of threads each ImageMagick operation can use while the batch is running. `0` means the available parallelism divided by the number of workers. It is ignored if another batch is already running.
+    pub magick_threads: usize,
+}
+
+impl Batch {
+    /// Create a `Batch` instance with default values.
+    /// ```rust,ignore
+    /// Batch {
+    ///     workers: 0usize,
+    ///     magick_threads: 0usize,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> Batch {
+        Batch {
+            workers: 0usize, magick_threads: 0usize
+        }
+    }
+
+    /// Run the jobs. A failed job does not abort the batch.
+    pub fn run(&self, jobs: Vec<BatchJob>) -> Result<BatchReport, MagickError> {
+        let _operation = begin_operation();
+
+        let parallelism = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+        let workers =
+            if self.workers == 0 { parallelism } else { self.workers }.min(jobs.len()).max(1);
+
+        let magick_threads =
+            if self.magick_threads == 0 { parallelism / workers } else { self.magick_threads }
+                .max(1);
+
+        let thread_limit = SharedThreadLimit::acquire(magick_threads)?;
+
+        let total = jobs.len();
+        let queue = Mutex::new(jobs.into_iter().enumerate());
+        let (tx, rx) = mpsc::channel();
+
+        let start = Instant::now();
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let queue = &queue;
+
+                scope.spawn(move || loop {
+                    let next = queue.lock().unwrap_or_else(|err| err.into_inner()).next();
+
+                    let (index, mut job) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+
+                    let job_start = Instant::now();
+
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        job.config.as_output_config().convert(&mut job.output, &job.input)
+                    }))
+                    .unwrap_or_else(|_| Err("The conversion job panicked.".into()));
+
+                    let result = BatchResult {
+                        output: job.output,
+                        result,
+                        elapsed: job_start.elapsed(),
+                    };
+
+                    if tx.send((index, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+        });
+
+        drop(thread_limit);
+        drop(tx);
+
+        let mut results: Vec<(usize, BatchResult)> = rx.into_iter().collect();
+
+        results.sort_by_key(|(index, _)| *index);
+
+        debug_assert_eq!(total, results.len());
+
+        let results: Vec<BatchResult> = results.into_iter().map(|(_, result)| result).collect();
+
+        let succeeded = results.iter().filter(|result| result.result.is_ok()).count();
+
+        let stats = BatchStats {
+            succeeded,
+            failed: results.len() - succeeded,
+            elapsed: start.elapsed(),
+            busy: results.iter().map(|result| result.elapsed).sum(),
+        };
+
+        Ok(BatchReport {
+            results,
+            stats,
+        })
+    }
+}
+
+impl Default for Batch {
+    #[inline]
+    fn default() -> Self {
+        Batch::new()
+    }
+}
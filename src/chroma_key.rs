@@ -0,0 +1,95 @@
+use magick_rust::{AlphaChannelOption, MagickError, MagickWand};
+
+use crate::functions::parse_color;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Make a color transparent. It is applied after the transform and before trimming, cropping and resizing.
+pub struct ChromaKey {
+    /// The color made transparent, such as `"#00FF00"`.
+    pub color:   String,
+    /// From 0 to 1, how far a color can be from `color` and still be fully transparent.
+    pub fuzz:    f64,
+    /// From 0 to 1, the distance beyond `fuzz` over which the transparency fades out, to soften the edges.
+    pub feather: f64,
+}
+
+impl ChromaKey {
+    /// Create a `ChromaKey` instance with default values.
+    /// ```rust,ignore
+    /// ChromaKey {
+    ///     color,
+    ///     fuzz: 0.1f64,
+    ///     feather: 0.05f64,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new<S: Into<String>>(color: S) -> ChromaKey {
+        ChromaKey {
+            color: color.into(), fuzz: 0.1f64, feather: 0.05f64
+        }
+    }
+
+    /// Apply the chroma key to an image.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        let key = parse_color(&self.color)?.map(f64::from);
+
+        let fuzz = self.fuzz.clamp(0f64, 1f64);
+        let feather = self.feather.clamp(0f64, 1f64);
+
+        let width = mw.get_image_width();
+        let height = mw.get_image_height();
+
+        mw.set_image_alpha_channel(AlphaChannelOption::Set)?;
+
+        let mut pixels = match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+            Some(pixels) => pixels,
+            None => return Err("Cannot export the pixels of the image.".into()),
+        };
+
+        // the largest distance between two colors
+        let max_distance = 255f64 * 3f64.sqrt();
+
+        for pixel in pixels.chunks_exact_mut(4) {
+            let distance = pixel
+                .iter()
+                .zip(key)
+                .map(|(sample, key)| (f64::from(*sample) - key).powi(2))
+                .sum::<f64>()
+                .sqrt()
+                / max_distance;
+
+            let factor = if distance <= fuzz {
+                0f64
+            } else if distance >= fuzz + feather {
+                continue;
+            } else {
+                (distance - fuzz) / feather
+            };
+
+            pixel[3] = (f64::from(pixel[3]) * factor).round() as u8;
+        }
+
+        mw.import_image_pixels(0, 0, width, height, &pixels, "RGBA")
+    }
+}
+
+/// Make every pixel either fully transparent or fully opaque, for formats which only have 1-bit alpha.
+pub(crate) fn apply_alpha_threshold(mw: &mut MagickWand, threshold: u8) -> Result<(), MagickError> {
+    if !mw.get_image_alpha_channel() {
+        return Ok(());
+    }
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let mut pixels = match mw.export_image_pixels(0, 0, width, height, "A") {
+        Some(pixels) => pixels,
+        None => return Err("Cannot export the pixels of the image.".into()),
+    };
+
+    for alpha in pixels.iter_mut() {
+        *alpha = if *alpha < threshold { 0 } else { 255 };
+    }
+
+    mw.import_image_pixels(0, 0, width, height, &pixels, "A")
+}
@@ -36,6 +36,20 @@ impl ColorName {
         }
     }
 
+    /// Get the RGB value of this color name.
+    pub const fn as_rgb(self) -> [u8; 3] {
+        match self {
+            Self::White => [255, 255, 255],
+            Self::Black => [0, 0, 0],
+            Self::Red => [255, 0, 0],
+            Self::Green => [0, 255, 0],
+            Self::Blue => [0, 0, 255],
+            Self::Yellow => [255, 255, 0],
+            Self::Cyan => [0, 255, 255],
+            Self::Magenta => [255, 0, 255],
+        }
+    }
+
     /// Get the static string slice of this color name.
     pub fn parse_str<S: AsRef<str>>(s: S) -> Option<Self> {
         let s = s.as_ref().to_lowercase();
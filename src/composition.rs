@@ -0,0 +1,358 @@
+use magick_rust::{CompositeOperator, FilterType, MagickError, MagickWand, PixelWand};
+
+use crate::{
+    compute_output_size, functions::fetch_trusted_magic_wand, image_config::ResizeConfig,
+    runtime::begin_operation, Crop, ImageResource,
+};
+
+/// How an image layer fits into its box.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayerFit {
+    /// Keep the original size of the image. The size of the box is ignored.
+    None,
+    /// Resize the image to fit inside the box, keeping its aspect ratio. The image is centered in the box.
+    Contain,
+    /// Resize and crop the image to cover the whole box, keeping its aspect ratio.
+    Cover,
+    /// Stretch the image to the size of the box.
+    Fill,
+}
+
+#[derive(Debug)]
+/// An image layer of a composition.
+pub struct ImageLayer {
+    /// The image. An SVG image is rasterized at the size it is drawn. It is trusted, so the read policy of the `ImageConvert` runtime does not apply to it.
+    pub image:  ImageResource,
+    /// The left position of the box.
+    pub x:      i32,
+    /// The top position of the box.
+    pub y:      i32,
+    /// The width of the box. `0` means the width of the canvas.
+    pub width:  u16,
+    /// The height of the box. `0` means the height of the canvas.
+    pub height: u16,
+    /// How the image fits into the box.
+    pub fit:    LayerFit,
+}
+
+impl ImageLayer {
+    /// Create an `ImageLayer` instance with default values.
+    /// ```rust,ignore
+    /// ImageLayer {
+    ///     image,
+    ///     x: 0i32,
+    ///     y: 0i32,
+    ///     width: 0u16,
+    ///     height: 0u16,
+    ///     fit: LayerFit::Cover,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new(image: ImageResource) -> ImageLayer {
+        ImageLayer {
+            image,
+            x: 0i32,
+            y: 0i32,
+            width: 0u16,
+            height: 0u16,
+            fit: LayerFit::Cover,
+        }
+    }
+}
+
+/// The alignment of the lines of a text layer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    #[inline]
+    fn gravity(self) -> &'static str {
+        match self {
+            Self::Left => "West",
+            Self::Center => "Center",
+            Self::Right => "East",
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A text layer of a composition.
+pub struct TextLayer {
+    /// The text. Use `\n` to break lines.
+    pub text:      String,
+    /// The path of a font file. `None` means the default font of ImageMagick.
+    pub font:      Option<String>,
+    /// The font size (in points).
+    pub size:      f64,
+    /// The text color, such as `"#FFFFFF"` or `"white"`.
+    pub color:     String,
+    /// The left position of the text box.
+    pub x:         i32,
+    /// The top position of the text box.
+    pub y:         i32,
+    /// The width of the text box. Lines are wrapped at this width. `0` means no wrapping.
+    pub width:     u16,
+    /// The alignment of the lines.
+    pub align:     TextAlign,
+    /// The maximum number of lines. The text is truncated with an ellipsis if it needs more lines. `0` means unlimited.
+    pub max_lines: usize,
+}
+
+impl TextLayer {
+    /// Create a `TextLayer` instance with default values.
+    /// ```rust,ignore
+    /// TextLayer {
+    ///     text,
+    ///     font: None,
+    ///     size: 32f64,
+    ///     color: String::from("#000000"),
+    ///     x: 0i32,
+    ///     y: 0i32,
+    ///     width: 0u16,
+    ///     align: TextAlign::Left,
+    ///     max_lines: 0usize,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new<S: Into<String>>(text: S) -> TextLayer {
+        TextLayer {
+            text:      text.into(),
+            font:      None,
+            size:      32f64,
+            color:     String::from("#000000"),
+            x:         0i32,
+            y:         0i32,
+            width:     0u16,
+            align:     TextAlign::Left,
+            max_lines: 0usize,
+        }
+    }
+}
+
+/// A layer of a composition.
+#[derive(Debug)]
+pub enum Layer {
+    Image(ImageLayer),
+    Text(TextLayer),
+}
+
+#[derive(Debug)]
+/// A canvas with layers drawn from the first to the last. The rendered `MagickWand` instance can be encoded by any `to_*` function through `ImageResource::MagickWand`.
+pub struct Composition {
+    /// The width of the canvas.
+    pub width:      u16,
+    /// The height of the canvas.
+    pub height:     u16,
+    /// The background color of the canvas, such as `"#FFFFFF"` or `"none"`.
+    pub background: String,
+    /// The layers.
+    pub layers:     Vec<Layer>,
+}
+
+impl Composition {
+    /// Create a `Composition` instance with default values.
+    /// ```rust,ignore
+    /// Composition {
+    ///     width,
+    ///     height,
+    ///     background: String::from("#FFFFFF"),
+    ///     layers: Vec::new(),
+    /// }
+    /// ```
+    #[inline]
+    pub fn new(width: u16, height: u16) -> Composition {
+        Composition {
+            width,
+            height,
+            background: String::from("#FFFFFF"),
+            layers: Vec::new(),
+        }
+    }
+
+    /// Render the composition.
+    pub fn render(&self) -> Result<MagickWand, MagickError> {
+        let _operation = begin_operation();
+
+        if self.width == 0 || self.height == 0 {
+            return Err("The size of the canvas is incorrect.".into());
+        }
+
+        let mw = MagickWand::new();
+
+        let mut pw = PixelWand::new();
+        pw.set_color(self.background.as_str())?;
+
+        mw.new_image(self.width as usize, self.height as usize, &pw)?;
+
+        for layer in self.layers.iter() {
+            match layer {
+                Layer::Image(layer) => draw_image_layer(&mw, layer, self.width, self.height)?,
+                Layer::Text(layer) => draw_text_layer(&mw, layer)?,
+            }
+        }
+
+        Ok(mw)
+    }
+}
+
+fn draw_image_layer(
+    mw: &MagickWand,
+    layer: &ImageLayer,
+    canvas_width: u16,
+    canvas_height: u16,
+) -> Result<(), MagickError> {
+    let box_width = if layer.width == 0 { canvas_width } else { layer.width };
+    let box_height = if layer.height == 0 { canvas_height } else { layer.height };
+
+    let config = match layer.fit {
+        LayerFit::None => ResizeConfig {
+            width: 0, height: 0, crop: None
+        },
+        LayerFit::Contain | LayerFit::Fill => {
+            ResizeConfig {
+                width: box_width, height: box_height, crop: None
+            }
+        },
+        LayerFit::Cover => ResizeConfig {
+            width:  box_width,
+            height: box_height,
+            crop:   Some(Crop::Center(f64::from(box_width), f64::from(box_height))),
+        },
+    };
+
+    let (lmw, vector) = fetch_trusted_magic_wand(&layer.image, &config)?;
+
+    let width = lmw.get_image_width();
+    let height = lmw.get_image_height();
+
+    match layer.fit {
+        LayerFit::None => (),
+        LayerFit::Fill => {
+            if width != box_width as usize || height != box_height as usize {
+                lmw.resize_image(box_width as usize, box_height as usize, FilterType::Lanczos)?;
+            }
+        },
+        LayerFit::Contain => {
+            if !vector {
+                if let Some((width, height)) =
+                    compute_output_size(false, width as u16, height as u16, box_width, box_height)
+                {
+                    lmw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+                }
+            }
+        },
+        LayerFit::Cover => {
+            let box_width = box_width as usize;
+            let box_height = box_height as usize;
+
+            if width != box_width || height != box_height {
+                let scale =
+                    (box_width as f64 / width as f64).max(box_height as f64 / height as f64);
+
+                let width = ((width as f64 * scale).ceil() as usize).max(box_width);
+                let height = ((height as f64 * scale).ceil() as usize).max(box_height);
+
+                lmw.resize_image(width, height, FilterType::Lanczos)?;
+
+                lmw.crop_image(
+                    box_width,
+                    box_height,
+                    ((width - box_width) / 2) as isize,
+                    ((height - box_height) / 2) as isize,
+                )?;
+            }
+        },
+    }
+
+    // center the image in its box
+    let (x, y) = match layer.fit {
+        LayerFit::None => (layer.x as isize, layer.y as isize),
+        _ => (
+            layer.x as isize + (box_width as isize - lmw.get_image_width() as isize) / 2,
+            layer.y as isize + (box_height as isize - lmw.get_image_height() as isize) / 2,
+        ),
+    };
+
+    mw.compose_images(&lmw, CompositeOperator::Over, true, x, y)
+}
+
+/// Escape a text so that ImageMagick does not interpret it as a file (`@file`) or as properties (`%[...]`).
+fn escape_text(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "%%");
+
+    if escaped.starts_with('@') {
+        format!("\\{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn render_caption(layer: &TextLayer, text: &str) -> Result<MagickWand, MagickError> {
+    let mut mw = MagickWand::new();
+
+    let mut pw = PixelWand::new();
+    pw.set_color("none")?;
+
+    mw.set_background_color(&pw)?;
+
+    mw.set_option("pointsize", layer.size.to_string().as_str())?;
+    mw.set_option("fill", layer.color.as_str())?;
+    mw.set_option("gravity", layer.align.gravity())?;
+
+    if let Some(font) = layer.font.as_ref() {
+        mw.set_option("font", font.as_str())?;
+    }
+
+    if layer.width > 0 {
+        mw.set_size(layer.width as usize, 0)?;
+    }
+
+    mw.read_image(format!("caption:{}", escape_text(text)).as_str())?;
+
+    Ok(mw)
+}
+
+fn draw_text_layer(mw: &MagickWand, layer: &TextLayer) -> Result<(), MagickError> {
+    if layer.text.is_empty() {
+        return Ok(());
+    }
+
+    let mut tmw = render_caption(layer, layer.text.as_str())?;
+
+    if layer.max_lines > 0 {
+        let line_height = render_caption(layer, "Ag")?.get_image_height();
+        let max_height = line_height * layer.max_lines + line_height / 2;
+
+        if tmw.get_image_height() > max_height {
+            let words: Vec<&str> = layer.text.split(' ').collect();
+
+            // find the most words which fit in the lines
+            let mut low = 0;
+            let mut high = words.len();
+            let mut fitted = render_caption(layer, "\u{2026}")?;
+
+            while low < high {
+                let middle = (low + high).div_ceil(2);
+
+                let text = format!("{}\u{2026}", words[..middle].join(" ").trim_end());
+
+                let cmw = render_caption(layer, text.as_str())?;
+
+                if cmw.get_image_height() > max_height {
+                    high = middle - 1;
+                } else {
+                    low = middle;
+                    fitted = cmw;
+                }
+            }
+
+            tmw = fitted;
+        }
+    }
+
+    mw.compose_images(&tmw, CompositeOperator::Over, true, layer.x as isize, layer.y as isize)
+}
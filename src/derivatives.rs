@@ -0,0 +1,364 @@
+use std::{cmp::Reverse, fs};
+
+use magick_rust::{FilterType, MagickError, MagickWand};
+
+use crate::{
+    compute_output_size_sharpen, fetch_magic_wand,
+    format_bmp::to_bmp_inner,
+    format_gif::to_gif_inner,
+    format_gray_raw::to_gray_raw_inner,
+    format_jpeg::to_jpg_inner,
+    format_pgm::to_pgm_inner,
+    format_png::to_png_inner,
+    format_tiff::to_tiff_inner,
+    format_webp::to_webp_inner,
+    functions::{fetch_magic_wand_vector, is_content_aware, Preparation},
+    image_config::compute_output_size_if_different,
+    runtime::begin_operation,
+    watermark::WatermarkCache,
+    AsImageInput, BMPConfig, Crop, GIFConfig, GrayRawConfig, ImageConfig, ImageInput,
+    ImageResource, JPGConfig, PGMConfig, PNGConfig, ReadPolicy, ResourceLimits, TIFFConfig,
+    WEBPConfig,
+};
+
+/// The output config of a derivative image.
+#[derive(Debug, Clone, Copy)]
+pub enum OutputConfig<'a> {
+    BMP(&'a BMPConfig),
+    GIF(&'a GIFConfig),
+    GrayRaw(&'a GrayRawConfig),
+    JPG(&'a JPGConfig),
+    PGM(&'a PGMConfig),
+    PNG(&'a PNGConfig),
+    TIFF(&'a TIFFConfig),
+    WEBP(&'a WEBPConfig),
+}
+
+macro_rules! dispatch {
+    ($output_config:expr, $config:ident => $e:expr) => {
+        match $output_config {
+            OutputConfig::BMP($config) => $e,
+            OutputConfig::GIF($config) => $e,
+            OutputConfig::GrayRaw($config) => $e,
+            OutputConfig::JPG($config) => $e,
+            OutputConfig::PGM($config) => $e,
+            OutputConfig::PNG($config) => $e,
+            OutputConfig::TIFF($config) => $e,
+            OutputConfig::WEBP($config) => $e,
+        }
+    };
+}
+
+impl<'a> OutputConfig<'a> {
+    #[inline]
+    fn preparation(&self) -> Preparation<'a> {
+        dispatch!(*self, config => Preparation::of(config))
+    }
+
+    #[inline]
+    fn is_content_aware(&self) -> bool {
+        dispatch!(self, config => is_content_aware(*config))
+    }
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&'a ReadPolicy> {
+        dispatch!(*self, config => config.get_read_policy())
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&'a ResourceLimits> {
+        dispatch!(*self, config => config.get_resource_limits())
+    }
+
+    #[inline]
+    fn compute_output_size_sharpen(&self, mw: &MagickWand) -> (u16, u16, f64) {
+        dispatch!(self, config => compute_output_size_sharpen(mw, *config))
+    }
+
+    #[inline]
+    fn compute_output_size_if_different(&self, mw: &MagickWand) -> Option<(u16, u16)> {
+        dispatch!(self, config => compute_output_size_if_different(mw, *config))
+    }
+
+    /// Read and prepare an input image for this output config.
+    #[inline]
+    pub(crate) fn fetch(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+    ) -> Result<(MagickWand, bool), MagickError> {
+        dispatch!(self, config => fetch_magic_wand(input, *config))
+    }
+
+    /// Convert an input image with this output config.
+    #[inline]
+    pub(crate) fn convert(
+        &self,
+        output: &mut ImageResource,
+        input: &(impl AsImageInput + ?Sized),
+    ) -> Result<(), MagickError> {
+        let (mw, vector) = self.fetch(input)?;
+
+        self.encode(output, mw, vector, &mut WatermarkCache::new())
+    }
+
+    pub(crate) fn encode(
+        &self,
+        output: &mut ImageResource,
+        mw: MagickWand,
+        vector: bool,
+        watermarks: &mut WatermarkCache,
+    ) -> Result<(), MagickError> {
+        match self {
+            OutputConfig::BMP(config) => to_bmp_inner(output, mw, vector, config, watermarks),
+            OutputConfig::GIF(config) => to_gif_inner(output, mw, vector, config, watermarks),
+            OutputConfig::GrayRaw(config) => {
+                to_gray_raw_inner(output, mw, vector, config, watermarks)
+            },
+            OutputConfig::JPG(config) => to_jpg_inner(output, mw, vector, config, watermarks),
+            OutputConfig::PGM(config) => to_pgm_inner(output, mw, vector, config, watermarks),
+            OutputConfig::PNG(config) => to_png_inner(output, mw, vector, config, watermarks),
+            OutputConfig::TIFF(config) => to_tiff_inner(output, mw, vector, config, watermarks),
+            OutputConfig::WEBP(config) => to_webp_inner(output, mw, vector, config, watermarks),
+        }
+    }
+}
+
+/// The config used to decode the input image of a `DerivativeSet`.
+#[derive(Debug)]
+struct DecodeConfig<'a> {
+    read_policy:     Option<&'a ReadPolicy>,
+    resource_limits: Option<&'a ResourceLimits>,
+}
+
+impl<'a> ImageConfig for DecodeConfig<'a> {
+    #[inline]
+    fn is_strip_metadata(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn get_width(&self) -> u16 {
+        0
+    }
+
+    #[inline]
+    fn get_height(&self) -> u16 {
+        0
+    }
+
+    #[inline]
+    fn get_crop(&self) -> Option<Crop> {
+        None
+    }
+
+    #[inline]
+    fn get_sharpen(&self) -> f64 {
+        0f64
+    }
+
+    #[inline]
+    fn is_shrink_only(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn respect_orientation(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&ReadPolicy> {
+        self.read_policy
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&ResourceLimits> {
+        self.resource_limits
+    }
+}
+
+/// An input image which is read and decoded only once, and then converted to many derivative images.
+#[derive(Debug)]
+pub struct DerivativeSet {
+    mw:  MagickWand,
+    svg: Option<String>,
+}
+
+impl DerivativeSet {
+    /// Read and decode an input image.
+    pub fn new(
+        input: &(impl AsImageInput + ?Sized),
+        read_policy: Option<&ReadPolicy>,
+        resource_limits: Option<&ResourceLimits>,
+    ) -> Result<DerivativeSet, MagickError> {
+        let _operation = begin_operation();
+
+        let config = DecodeConfig {
+            read_policy,
+            resource_limits,
+        };
+
+        let (mw, _) = fetch_magic_wand(input, &config)?;
+
+        // keep the source of a vector image so that it can be rasterized again at each output size
+        let svg = match mw.get_image_format()?.as_str() {
+            "SVG" | "MVG" => match input.as_image_input()? {
+                ImageInput::Path(p) => fs::read_to_string(p).ok(),
+                ImageInput::Data(b) => String::from_utf8(b.to_vec()).ok(),
+                ImageInput::MagickWand(_) => None,
+            },
+            _ => None,
+        };
+
+        Ok(DerivativeSet {
+            mw,
+            svg,
+        })
+    }
+
+    fn base_index<'a>(
+        &self,
+        bases: &mut Vec<(Preparation<'a>, MagickWand)>,
+        preparation: Preparation<'a>,
+    ) -> Result<usize, MagickError> {
+        if let Some(index) = bases.iter().position(|(p, _)| *p == preparation) {
+            return Ok(index);
+        }
+
+        let mut mw = self.mw.clone();
+
+        preparation.apply(&mut mw)?;
+
+        bases.push((preparation, mw));
+
+        Ok(bases.len() - 1)
+    }
+
+    /// Get the size of the decoded image after it is prepared.
+    pub(crate) fn prepared_size(
+        &self,
+        preparation: Preparation,
+    ) -> Result<(u16, u16), MagickError> {
+        let mut bases = Vec::with_capacity(1);
+
+        let index = self.base_index(&mut bases, preparation)?;
+
+        let mw = &bases[index].1;
+
+        Ok((mw.get_image_width() as u16, mw.get_image_height() as u16))
+    }
+
+    /// Convert the decoded image to derivative images. Outputs are produced from the largest to the smallest, and each one is resized from the smallest intermediate image which is not smaller than it.
+    pub fn convert(
+        &self,
+        outputs: &mut [(OutputConfig, &mut ImageResource)],
+    ) -> Result<(), MagickError> {
+        let _operation = begin_operation();
+
+        let mut bases: Vec<(Preparation, MagickWand)> = Vec::new();
+
+        // the watermarks are decoded only once for all the outputs
+        let mut watermarks = WatermarkCache::new();
+
+        if let Some(svg) = self.svg.as_ref() {
+            for (config, output) in outputs.iter_mut() {
+                let preparation = config.preparation();
+
+                let index = self.base_index(&mut bases, preparation)?;
+
+                let mw = bases[index].1.clone();
+
+                let (mw, vector) = match config.compute_output_size_if_different(&mw) {
+                    // seam carving needs the raster image to be resized
+                    _ if config.is_content_aware() => (mw, false),
+                    Some((new_width, new_height)) => {
+                        if (new_width as usize) < mw.get_image_width() {
+                            // TODO ImageMagick handles the smaller size of SVG poorly, so just do resize
+                            (mw, false)
+                        } else {
+                            fetch_magic_wand_vector(
+                                mw,
+                                new_width,
+                                new_height,
+                                svg.clone(),
+                                &preparation,
+                            )?
+                        }
+                    },
+                    None => (mw, true),
+                };
+
+                config.encode(output, mw, vector, &mut watermarks)?;
+            }
+
+            return Ok(());
+        }
+
+        let mut targets = Vec::with_capacity(outputs.len());
+
+        for (i, (config, _)) in outputs.iter().enumerate() {
+            let index = self.base_index(&mut bases, config.preparation())?;
+
+            let (width, height, sharpen) = config.compute_output_size_sharpen(&bases[index].1);
+
+            targets.push((i, index, width as usize, height as usize, sharpen));
+        }
+
+        targets.sort_by_key(|(_, _, width, height, _)| Reverse(width * height));
+
+        // resized but not yet sharpened images
+        let mut intermediates: Vec<(Preparation, MagickWand)> = Vec::new();
+
+        for (i, index, width, height, sharpen) in targets {
+            let (key, base) = &bases[index];
+
+            // a content-aware resize cannot start from an intermediate image, so let the encoder resize the base image
+            if outputs[i].0.is_content_aware() {
+                let (config, output) = &mut outputs[i];
+
+                config.encode(output, base.clone(), false, &mut watermarks)?;
+
+                continue;
+            }
+
+            let source = intermediates
+                .iter()
+                .filter(|(k, mw)| {
+                    k == key && mw.get_image_width() >= width && mw.get_image_height() >= height
+                })
+                .min_by_key(|(_, mw)| mw.get_image_width() * mw.get_image_height())
+                .map(|(_, mw)| mw)
+                .unwrap_or(base);
+
+            let mw = source.clone();
+
+            if mw.get_image_width() != width || mw.get_image_height() != height {
+                mw.resize_image(width, height, FilterType::Lanczos)?;
+            }
+
+            intermediates.push((*key, mw.clone()));
+
+            mw.sharpen_image(0f64, sharpen)?;
+
+            let (config, output) = &mut outputs[i];
+
+            config.encode(output, mw, true, &mut watermarks)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode an image once and convert it to many derivative images. The read policy and the resource limits of the first output config are used to read the input image.
+pub fn convert_many(
+    input: &(impl AsImageInput + ?Sized),
+    outputs: &mut [(OutputConfig, &mut ImageResource)],
+) -> Result<(), MagickError> {
+    let config = match outputs.first() {
+        Some((config, _)) => *config,
+        None => return Ok(()),
+    };
+
+    DerivativeSet::new(input, config.get_read_policy(), config.get_resource_limits())?
+        .convert(outputs)
+}
@@ -0,0 +1,196 @@
+use magick_rust::{MagickError, MagickWand};
+
+use crate::functions::{map_pixels, parse_color};
+
+/// The coefficients used to compute the luma of a color.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Luma {
+    /// ITU-R BT.601, `0.299R + 0.587G + 0.114B`.
+    Rec601,
+    /// ITU-R BT.709, `0.2126R + 0.7152G + 0.0722B`.
+    Rec709,
+    /// The average of the three channels.
+    Average,
+}
+
+impl Luma {
+    #[inline]
+    fn coefficients(self) -> [f64; 3] {
+        match self {
+            Self::Rec601 => [0.299, 0.587, 0.114],
+            Self::Rec709 => [0.2126, 0.7152, 0.0722],
+            Self::Average => [1f64 / 3f64; 3],
+        }
+    }
+
+    #[inline]
+    fn compute(self, pixel: &[f64]) -> f64 {
+        let [r, g, b] = self.coefficients();
+
+        pixel[0] * r + pixel[1] * g + pixel[2] * b
+    }
+}
+
+/// A creative filter applied after resizing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Convert the image to grayscale.
+    Grayscale(Luma),
+    /// From 0 to 1, the strength of a sepia tone.
+    Sepia(f64),
+    /// A gaussian blur. The value is the sigma (in pixels).
+    Blur(f64),
+    /// Replace the image with square blocks of their average colors. The value is the size of a block (in pixels).
+    Pixelate(u16),
+    /// Map the dark tones to the first color and the light tones to the second color, such as `"#000080"` and `"#FFFF00"`.
+    Duotone(String, String),
+    /// Reduce the number of levels of each channel. The value is the number of levels, from 2 to 255.
+    Posterize(u8),
+    /// From 0 to 1, the strength of darkening the corners.
+    Vignette(f64),
+    /// Invert the colors.
+    Invert,
+}
+
+impl Effect {
+    /// Apply the effect to an image. The samples are not quantized to 8 bits between the effects.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        match self {
+            Self::Grayscale(luma) => map_pixels(mw, |pixels, _, _| {
+                for pixel in pixels.chunks_exact_mut(3) {
+                    let y = luma.compute(pixel);
+
+                    pixel.fill(y);
+                }
+            }),
+            Self::Sepia(strength) => {
+                let strength = strength.clamp(0f64, 1f64);
+
+                if strength == 0f64 {
+                    return Ok(());
+                }
+
+                map_pixels(mw, |pixels, _, _| {
+                    for pixel in pixels.chunks_exact_mut(3) {
+                        let [r, g, b] = [pixel[0], pixel[1], pixel[2]];
+
+                        let sepia = [
+                            r * 0.393 + g * 0.769 + b * 0.189,
+                            r * 0.349 + g * 0.686 + b * 0.168,
+                            r * 0.272 + g * 0.534 + b * 0.131,
+                        ];
+
+                        for (sample, sepia) in pixel.iter_mut().zip(sepia) {
+                            *sample += (sepia.min(1f64) - *sample) * strength;
+                        }
+                    }
+                })
+            },
+            Self::Blur(sigma) => {
+                if *sigma <= 0f64 {
+                    return Ok(());
+                }
+
+                mw.gaussian_blur_image(0f64, *sigma)
+            },
+            Self::Pixelate(size) => {
+                let size = *size as usize;
+
+                if size <= 1 {
+                    return Ok(());
+                }
+
+                map_pixels(mw, |pixels, width, height| pixelate(pixels, width, height, size))
+            },
+            Self::Duotone(dark, light) => {
+                let dark = parse_color(dark)?.map(|v| f64::from(v) / 255f64);
+                let light = parse_color(light)?.map(|v| f64::from(v) / 255f64);
+
+                map_pixels(mw, |pixels, _, _| {
+                    for pixel in pixels.chunks_exact_mut(3) {
+                        let t = Luma::Rec601.compute(pixel);
+
+                        for ((sample, dark), light) in pixel.iter_mut().zip(dark).zip(light) {
+                            *sample = dark + (light - dark) * t;
+                        }
+                    }
+                })
+            },
+            Self::Posterize(levels) => {
+                if *levels < 2 {
+                    return Err("The number of posterize levels must be from 2 to 255.".into());
+                }
+
+                let steps = f64::from(levels - 1);
+
+                map_pixels(mw, |pixels, _, _| {
+                    for sample in pixels.iter_mut() {
+                        *sample = (*sample * steps).round() / steps;
+                    }
+                })
+            },
+            Self::Vignette(strength) => {
+                let strength = strength.clamp(0f64, 1f64);
+
+                if strength == 0f64 {
+                    return Ok(());
+                }
+
+                map_pixels(mw, |pixels, width, height| {
+                    let half_width = width as f64 / 2f64;
+                    let half_height = height as f64 / 2f64;
+
+                    for (i, pixel) in pixels.chunks_exact_mut(3).enumerate() {
+                        let dx = ((i % width) as f64 + 0.5 - half_width) / half_width;
+                        let dy = ((i / width) as f64 + 0.5 - half_height) / half_height;
+
+                        // the squared distance is about 2 at the corners
+                        let factor = (1f64 - strength * (dx * dx + dy * dy) / 2f64).max(0f64);
+
+                        for sample in pixel.iter_mut() {
+                            *sample *= factor;
+                        }
+                    }
+                })
+            },
+            Self::Invert => map_pixels(mw, |pixels, _, _| {
+                for sample in pixels.iter_mut() {
+                    *sample = 1f64 - *sample;
+                }
+            }),
+        }
+    }
+}
+
+fn pixelate(pixels: &mut [f64], width: usize, height: usize, size: usize) {
+    for top in (0..height).step_by(size) {
+        let bottom = (top + size).min(height);
+
+        for left in (0..width).step_by(size) {
+            let right = (left + size).min(width);
+
+            let mut sums = [0f64; 3];
+
+            for y in top..bottom {
+                for x in left..right {
+                    let i = (y * width + x) * 3;
+
+                    for (sum, sample) in sums.iter_mut().zip(&pixels[i..i + 3]) {
+                        *sum += *sample;
+                    }
+                }
+            }
+
+            let count = ((bottom - top) * (right - left)) as f64;
+            let average = sums.map(|sum| sum / count);
+
+            for y in top..bottom {
+                for x in left..right {
+                    let i = (y * width + x) * 3;
+
+                    pixels[i..i + 3].copy_from_slice(&average);
+                }
+            }
+        }
+    }
+}
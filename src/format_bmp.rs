@@ -1,9 +1,16 @@
-use magick_rust::{AlphaChannelOption, FilterType, MagickError, PixelWand, ResolutionType};
+use std::io::Write;
+
+use magick_rust::{AlphaChannelOption, MagickError, MagickWand, PixelWand, ResolutionType};
 use str_utils::EndsWithIgnoreAsciiCase;
 
 use crate::{
-    compute_output_size_sharpen, fetch_magic_wand, ColorName, Crop, ImageConfig, ImageResource,
-    InterlaceType,
+    compute_output_size_sharpen, fetch_magic_wand,
+    functions::{handle_post_resize, handle_resize},
+    runtime::begin_operation,
+    watermark::WatermarkCache,
+    Adjustments, AsImageInput, ChromaKey, ColorName, Crop, Effect, ImageConfig, ImageResource,
+    InterlaceType, Lut, PlaceholderSink, ReadPolicy, ResizeMode, ResourceLimits, Shape, Transform,
+    Trim, Watermark,
 };
 
 #[derive(Debug)]
@@ -21,12 +28,38 @@ pub struct BMPConfig {
     pub shrink_only:         bool,
     /// The higher the sharper. A negative value means auto adjustment.
     pub sharpen:             f64,
-    /// Apply orientation from image metadata if available.
+    /// Apply orientation from image metadata if available, whether the image is read from a file, from data or from a `MagickWand` instance.
     pub respect_orientation: bool,
     /// The color is used for fill up the alpha background.
     pub background_color:    Option<ColorName>,
     /// Pixels per inch.
     pub ppi:                 Option<(f64, f64)>,
+    /// Restrict how the input image can be read.
+    pub read_policy:         Option<ReadPolicy>,
+    /// Limit the resources used to read the input image.
+    pub resource_limits:     Option<ResourceLimits>,
+    /// Draw a watermark after resizing.
+    pub watermark:           Option<Watermark>,
+    /// Rotate and mirror the image before cropping and resizing.
+    pub transform:           Option<Transform>,
+    /// Adjust the tone and the colors after resizing.
+    pub adjustments:         Option<Adjustments>,
+    /// Apply effects in order after resizing.
+    pub effects:             Vec<Effect>,
+    /// Grade the colors with a 3D LUT after resizing.
+    pub lut:                 Option<Lut>,
+    /// Mask the image with a shape after resizing.
+    pub shape:               Option<Shape>,
+    /// Trim uniform borders or transparent edges before cropping and resizing.
+    pub trim:                Option<Trim>,
+    /// Make a color transparent before trimming, cropping and resizing.
+    pub chroma_key:          Option<ChromaKey>,
+    /// Make the pixels whose alpha is lower than this value (from 0 to 255) fully transparent, and the others fully opaque.
+    pub alpha_threshold:     Option<u8>,
+    /// How the image is resized.
+    pub resize_mode:         ResizeMode,
+    /// Compute a placeholder hash (BlurHash or ThumbHash) from the resized image.
+    pub placeholder:         Option<PlaceholderSink>,
 }
 
 impl BMPConfig {
@@ -42,6 +75,19 @@ impl BMPConfig {
     ///     respect_orientation: false,
     ///     background_color: None,
     /// ppi: None,
+    ///     read_policy: None,
+    ///     resource_limits: None,
+    ///     watermark: None,
+    ///     transform: None,
+    ///     adjustments: None,
+    ///     effects: Vec::new(),
+    ///     lut: None,
+    ///     shape: None,
+    ///     trim: None,
+    ///     chroma_key: None,
+    ///     alpha_threshold: None,
+    ///     resize_mode: ResizeMode::Resample,
+    ///     placeholder: None,
     /// }
     /// ```
     #[inline]
@@ -56,6 +102,19 @@ impl BMPConfig {
             respect_orientation: false,
             background_color:    None,
             ppi:                 None,
+            read_policy:         None,
+            resource_limits:     None,
+            watermark:           None,
+            transform:           None,
+            adjustments:         None,
+            effects:             Vec::new(),
+            lut:                 None,
+            shape:               None,
+            trim:                None,
+            chroma_key:          None,
+            alpha_threshold:     None,
+            resize_mode:         ResizeMode::Resample,
+            placeholder:         None,
         }
     }
 }
@@ -102,16 +161,94 @@ impl ImageConfig for BMPConfig {
     fn respect_orientation(&self) -> bool {
         self.respect_orientation
     }
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&ReadPolicy> {
+        self.read_policy.as_ref()
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&ResourceLimits> {
+        self.resource_limits.as_ref()
+    }
+
+    #[inline]
+    fn get_watermark(&self) -> Option<&Watermark> {
+        self.watermark.as_ref()
+    }
+
+    #[inline]
+    fn get_transform(&self) -> Option<&Transform> {
+        self.transform.as_ref()
+    }
+
+    #[inline]
+    fn get_adjustments(&self) -> Option<&Adjustments> {
+        self.adjustments.as_ref()
+    }
+
+    #[inline]
+    fn get_effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    #[inline]
+    fn get_lut(&self) -> Option<&Lut> {
+        self.lut.as_ref()
+    }
+
+    #[inline]
+    fn get_shape(&self) -> Option<&Shape> {
+        self.shape.as_ref()
+    }
+
+    #[inline]
+    fn get_trim(&self) -> Option<&Trim> {
+        self.trim.as_ref()
+    }
+
+    #[inline]
+    fn get_chroma_key(&self) -> Option<&ChromaKey> {
+        self.chroma_key.as_ref()
+    }
+
+    #[inline]
+    fn get_alpha_threshold(&self) -> Option<u8> {
+        self.alpha_threshold
+    }
+
+    #[inline]
+    fn get_resize_mode(&self) -> ResizeMode {
+        self.resize_mode
+    }
+
+    #[inline]
+    fn get_placeholder(&self) -> Option<&PlaceholderSink> {
+        self.placeholder.as_ref()
+    }
 }
 
 /// Convert an image to a BMP image.
 pub fn to_bmp(
     output: &mut ImageResource,
-    input: &ImageResource,
+    input: &(impl AsImageInput + ?Sized),
     config: &BMPConfig,
 ) -> Result<(), MagickError> {
-    let (mut mw, vector) = fetch_magic_wand(input, config)?;
+    let _operation = begin_operation();
+
+    let (mw, vector) = fetch_magic_wand(input, config)?;
+
+    to_bmp_inner(output, mw, vector, config, &mut WatermarkCache::new())
+}
 
+/// Encode a fetched image. If `vector` is `true`, the image is already at its output size.
+pub(crate) fn to_bmp_inner(
+    output: &mut ImageResource,
+    mut mw: MagickWand,
+    vector: bool,
+    config: &BMPConfig,
+    watermarks: &mut WatermarkCache,
+) -> Result<(), MagickError> {
     if let Some(background_color) = config.background_color {
         let mut pw = PixelWand::new();
         pw.set_color(background_color.as_str())?;
@@ -122,11 +259,13 @@ pub fn to_bmp(
     if !vector {
         let (width, height, sharpen) = compute_output_size_sharpen(&mw, config);
 
-        mw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+        handle_resize(&mw, config, width, height)?;
 
         mw.sharpen_image(0f64, sharpen)?;
     }
 
+    handle_post_resize(&mut mw, config, watermarks)?;
+
     if config.strip_metadata {
         mw.strip_image()?;
     }
@@ -152,7 +291,17 @@ pub fn to_bmp(
         },
         ImageResource::Data(b) => {
             let mut temp = mw.write_image_blob("BMP")?;
-            b.append(&mut temp);
+
+            if b.is_empty() {
+                *b = temp;
+            } else {
+                b.append(&mut temp);
+            }
+        },
+        ImageResource::Writer(w) => {
+            let temp = mw.write_image_blob("BMP")?;
+
+            w.write_all(&temp).and_then(|_| w.flush()).map_err(|_| "Cannot write the bmp data.")?;
         },
         ImageResource::MagickWand(mw_2) => {
             *mw_2 = mw;
@@ -0,0 +1,424 @@
+use std::io::Write;
+
+use magick_rust::{MagickError, MagickWand};
+use str_utils::EndsWithIgnoreAsciiCase;
+
+use crate::{
+    compute_output_size_sharpen, fetch_magic_wand,
+    functions::{extend_image_center, handle_post_resize, handle_resize},
+    runtime::begin_operation,
+    watermark::WatermarkCache,
+    Adjustments, AsImageInput, ChromaKey, Crop, Effect, ImageConfig, ImageResource, Lut,
+    PlaceholderSink, ReadPolicy, ResizeMode, ResourceLimits, Shape, Transform, Trim, Watermark,
+};
+
+/// The icon types of an ICNS image. Every icon type is stored as a PNG image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ICNSIconType {
+    /// 16x16 (`icp4`).
+    Icon16,
+    /// 16x16@2x, 32x32 pixels (`ic11`).
+    Icon16At2x,
+    /// 32x32 (`icp5`).
+    Icon32,
+    /// 32x32@2x, 64x64 pixels (`ic12`).
+    Icon32At2x,
+    /// 64x64 (`icp6`).
+    Icon64,
+    /// 128x128 (`ic07`).
+    Icon128,
+    /// 128x128@2x, 256x256 pixels (`ic13`).
+    Icon128At2x,
+    /// 256x256 (`ic08`).
+    Icon256,
+    /// 256x256@2x, 512x512 pixels (`ic14`).
+    Icon256At2x,
+    /// 512x512 (`ic09`).
+    Icon512,
+    /// 512x512@2x, 1024x1024 pixels (`ic10`).
+    Icon512At2x,
+}
+
+impl ICNSIconType {
+    /// Get the width (and the height) of this icon type in pixels.
+    pub const fn pixel_size(self) -> u16 {
+        match self {
+            Self::Icon16 => 16,
+            Self::Icon16At2x | Self::Icon32 => 32,
+            Self::Icon32At2x | Self::Icon64 => 64,
+            Self::Icon128 => 128,
+            Self::Icon128At2x | Self::Icon256 => 256,
+            Self::Icon256At2x | Self::Icon512 => 512,
+            Self::Icon512At2x => 1024,
+        }
+    }
+
+    /// Get the OSType code of this icon type.
+    pub const fn os_type(self) -> &'static [u8; 4] {
+        match self {
+            Self::Icon16 => b"icp4",
+            Self::Icon16At2x => b"ic11",
+            Self::Icon32 => b"icp5",
+            Self::Icon32At2x => b"ic12",
+            Self::Icon64 => b"icp6",
+            Self::Icon128 => b"ic07",
+            Self::Icon128At2x => b"ic13",
+            Self::Icon256 => b"ic08",
+            Self::Icon256At2x => b"ic14",
+            Self::Icon512 => b"ic09",
+            Self::Icon512At2x => b"ic10",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ICNSConfigInner<'a> {
+    config: &'a ICNSConfig,
+    size:   u16,
+}
+
+#[derive(Debug)]
+/// The output config of an ICNS image.
+pub struct ICNSConfig {
+    /// Remove the metadata stored in the input image.
+    pub strip_metadata:      bool,
+    /// The icon types of the output image.
+    pub icon_types:          Vec<ICNSIconType>,
+    /// Crop the image.
+    pub crop:                Option<Crop>,
+    /// The higher the sharper. A negative value means auto adjustment.
+    pub sharpen:             f64,
+    /// Apply orientation from image metadata if available, whether the image is read from a file, from data or from a `MagickWand` instance.
+    pub respect_orientation: bool,
+    /// Restrict how the input image can be read.
+    pub read_policy:         Option<ReadPolicy>,
+    /// Limit the resources used to read the input image.
+    pub resource_limits:     Option<ResourceLimits>,
+    /// Draw a watermark after resizing.
+    pub watermark:           Option<Watermark>,
+    /// Rotate and mirror the image before cropping and resizing.
+    pub transform:           Option<Transform>,
+    /// Adjust the tone and the colors after resizing.
+    pub adjustments:         Option<Adjustments>,
+    /// Apply effects in order after resizing.
+    pub effects:             Vec<Effect>,
+    /// Grade the colors with a 3D LUT after resizing.
+    pub lut:                 Option<Lut>,
+    /// Mask the image with a shape after resizing.
+    pub shape:               Option<Shape>,
+    /// Trim uniform borders or transparent edges before cropping and resizing.
+    pub trim:                Option<Trim>,
+    /// Make a color transparent before trimming, cropping and resizing.
+    pub chroma_key:          Option<ChromaKey>,
+    /// Make the pixels whose alpha is lower than this value (from 0 to 255) fully transparent, and the others fully opaque.
+    pub alpha_threshold:     Option<u8>,
+    /// How the image is resized.
+    pub resize_mode:         ResizeMode,
+    /// Compute a placeholder hash (BlurHash or ThumbHash) from the resized image.
+    pub placeholder:         Option<PlaceholderSink>,
+}
+
+impl ICNSConfig {
+    /// Create a `ICNSConfig` instance with default values.
+    /// ```rust,ignore
+    /// ICNSConfig {
+    ///     strip_metadata: true,
+    ///     icon_types: vec![
+    ///         ICNSIconType::Icon16,
+    ///         ICNSIconType::Icon16At2x,
+    ///         ICNSIconType::Icon32,
+    ///         ICNSIconType::Icon32At2x,
+    ///         ICNSIconType::Icon128,
+    ///         ICNSIconType::Icon128At2x,
+    ///         ICNSIconType::Icon256,
+    ///         ICNSIconType::Icon256At2x,
+    ///         ICNSIconType::Icon512,
+    ///         ICNSIconType::Icon512At2x,
+    ///     ],
+    ///     crop: None,
+    ///     sharpen: -1f64,
+    ///     respect_orientation: false,
+    ///     read_policy: None,
+    ///     resource_limits: None,
+    ///     watermark: None,
+    ///     transform: None,
+    ///     adjustments: None,
+    ///     effects: Vec::new(),
+    ///     lut: None,
+    ///     shape: None,
+    ///     trim: None,
+    ///     chroma_key: None,
+    ///     alpha_threshold: None,
+    ///     resize_mode: ResizeMode::Resample,
+    ///     placeholder: None,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new() -> ICNSConfig {
+        ICNSConfig {
+            strip_metadata:      true,
+            icon_types:          vec![
+                ICNSIconType::Icon16,
+                ICNSIconType::Icon16At2x,
+                ICNSIconType::Icon32,
+                ICNSIconType::Icon32At2x,
+                ICNSIconType::Icon128,
+                ICNSIconType::Icon128At2x,
+                ICNSIconType::Icon256,
+                ICNSIconType::Icon256At2x,
+                ICNSIconType::Icon512,
+                ICNSIconType::Icon512At2x,
+            ],
+            crop:                None,
+            sharpen:             -1f64,
+            respect_orientation: false,
+            read_policy:         None,
+            resource_limits:     None,
+            watermark:           None,
+            transform:           None,
+            adjustments:         None,
+            effects:             Vec::new(),
+            lut:                 None,
+            shape:               None,
+            trim:                None,
+            chroma_key:          None,
+            alpha_threshold:     None,
+            resize_mode:         ResizeMode::Resample,
+            placeholder:         None,
+        }
+    }
+}
+
+impl Default for ICNSConfig {
+    #[inline]
+    fn default() -> Self {
+        ICNSConfig::new()
+    }
+}
+
+impl<'a> ImageConfig for ICNSConfigInner<'a> {
+    #[inline]
+    fn is_strip_metadata(&self) -> bool {
+        self.config.strip_metadata
+    }
+
+    #[inline]
+    fn get_width(&self) -> u16 {
+        self.size
+    }
+
+    #[inline]
+    fn get_height(&self) -> u16 {
+        self.size
+    }
+
+    #[inline]
+    fn get_crop(&self) -> Option<Crop> {
+        self.config.crop
+    }
+
+    #[inline]
+    fn get_sharpen(&self) -> f64 {
+        self.config.sharpen
+    }
+
+    #[inline]
+    fn is_shrink_only(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn respect_orientation(&self) -> bool {
+        self.config.respect_orientation
+    }
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&ReadPolicy> {
+        self.config.read_policy.as_ref()
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&ResourceLimits> {
+        self.config.resource_limits.as_ref()
+    }
+
+    #[inline]
+    fn get_watermark(&self) -> Option<&Watermark> {
+        self.config.watermark.as_ref()
+    }
+
+    #[inline]
+    fn get_transform(&self) -> Option<&Transform> {
+        self.config.transform.as_ref()
+    }
+
+    #[inline]
+    fn get_adjustments(&self) -> Option<&Adjustments> {
+        self.config.adjustments.as_ref()
+    }
+
+    #[inline]
+    fn get_effects(&self) -> &[Effect] {
+        &self.config.effects
+    }
+
+    #[inline]
+    fn get_lut(&self) -> Option<&Lut> {
+        self.config.lut.as_ref()
+    }
+
+    #[inline]
+    fn get_shape(&self) -> Option<&Shape> {
+        self.config.shape.as_ref()
+    }
+
+    #[inline]
+    fn get_trim(&self) -> Option<&Trim> {
+        self.config.trim.as_ref()
+    }
+
+    #[inline]
+    fn get_chroma_key(&self) -> Option<&ChromaKey> {
+        self.config.chroma_key.as_ref()
+    }
+
+    #[inline]
+    fn get_alpha_threshold(&self) -> Option<u8> {
+        self.config.alpha_threshold
+    }
+
+    #[inline]
+    fn get_resize_mode(&self) -> ResizeMode {
+        self.config.resize_mode
+    }
+
+    #[inline]
+    fn get_placeholder(&self) -> Option<&PlaceholderSink> {
+        self.config.placeholder.as_ref()
+    }
+}
+
+fn add_icns_element(
+    icns: &mut Vec<u8>,
+    mut mw: MagickWand,
+    icon_type: ICNSIconType,
+    config: &ICNSConfigInner,
+    resize: bool,
+    watermarks: &mut WatermarkCache,
+) -> Result<(), MagickError> {
+    if resize {
+        let (width, height, sharpen) = compute_output_size_sharpen(&mw, config);
+
+        handle_resize(&mw, config, width, height)?;
+
+        mw.sharpen_image(0f64, sharpen)?;
+    }
+
+    handle_post_resize(&mut mw, config, watermarks)?;
+
+    let size = config.size as usize;
+
+    if mw.get_image_width() != size || mw.get_image_height() != size {
+        extend_image_center(&mw, size, size)?;
+    }
+
+    mw.set_image_format("PNG")?;
+
+    let data = mw.write_image_blob("PNG")?;
+
+    icns.extend_from_slice(icon_type.os_type());
+    icns.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+    icns.extend_from_slice(&data);
+
+    Ok(())
+}
+
+/// Convert an image to an ICNS image (an Apple icon image).
+pub fn to_icns(
+    output: &mut ImageResource,
+    input: &(impl AsImageInput + ?Sized),
+    config: &ICNSConfig,
+) -> Result<(), MagickError> {
+    let _operation = begin_operation();
+
+    // the header (magic number and file length) is filled after all elements are added
+    let mut icns = vec![0u8; 8];
+
+    let mut icon_types = config.icon_types.clone();
+
+    // start from the largest size, so that a vector image is rasterized at the largest size first
+    icon_types.sort_by_key(|icon_type| u16::MAX - icon_type.pixel_size());
+
+    let icns_config_inner: Vec<ICNSConfigInner> = icon_types
+        .iter()
+        .map(|icon_type| ICNSConfigInner {
+            config,
+            size: icon_type.pixel_size(),
+        })
+        .collect();
+
+    let mut iter = icon_types.iter().copied().zip(icns_config_inner.iter());
+
+    // the watermark is decoded only once for all the elements
+    let mut watermarks = WatermarkCache::new();
+
+    if let Some((icon_type, config)) = iter.next() {
+        let (mw, vector) = fetch_magic_wand(input, config)?;
+
+        if config.is_strip_metadata() {
+            mw.strip_image()?;
+        }
+
+        if vector {
+            add_icns_element(&mut icns, mw, icon_type, config, false, &mut watermarks)?;
+
+            for (icon_type, config) in iter {
+                let (mw, vector) = fetch_magic_wand(input, config)?;
+
+                if config.is_strip_metadata() {
+                    mw.strip_image()?;
+                }
+
+                add_icns_element(&mut icns, mw, icon_type, config, !vector, &mut watermarks)?;
+            }
+        } else {
+            add_icns_element(&mut icns, mw.clone(), icon_type, config, true, &mut watermarks)?;
+
+            for (icon_type, config) in iter {
+                add_icns_element(&mut icns, mw.clone(), icon_type, config, true, &mut watermarks)?;
+            }
+        }
+    }
+
+    let length = icns.len() as u32;
+
+    icns[..4].copy_from_slice(b"icns");
+    icns[4..8].copy_from_slice(&length.to_be_bytes());
+
+    match output {
+        ImageResource::Path(p) => {
+            if !p.ends_with_ignore_ascii_case_with_lowercase(".icns") {
+                return Err("The file extension name is not icns.".into());
+            }
+
+            std::fs::write(p, icns).map_err(|_| "Cannot write the icns file.")?;
+        },
+        ImageResource::Data(b) => {
+            if b.is_empty() {
+                *b = icns;
+            } else {
+                b.append(&mut icns);
+            }
+        },
+        ImageResource::Writer(w) => {
+            w.write_all(&icns)
+                .and_then(|_| w.flush())
+                .map_err(|_| "Cannot write the icns data.")?;
+        },
+        ImageResource::MagickWand(_) => {
+            return Err("ICNS cannot be output to a MagickWand instance.".into());
+        },
+    }
+
+    Ok(())
+}
@@ -1,32 +1,32 @@
-use magick_rust::{FilterType, MagickError};
+use magick_rust::{MagickError, MagickWand};
 use str_utils::EndsWithIgnoreAsciiCase;
 
-use crate::{compute_output_size_sharpen, fetch_magic_wand, Crop, ImageConfig, ImageResource};
+use crate::{
+    compute_output_size_sharpen, fetch_magic_wand,
+    functions::{extend_image_center, handle_post_resize, handle_resize},
+    runtime::begin_operation,
+    watermark::WatermarkCache,
+    Adjustments, AsImageInput, ChromaKey, Crop, Effect, ImageConfig, ImageInput, ImageResource,
+    Lut, PlaceholderSink, ReadPolicy, ResizeMode, ResourceLimits, Shape, Transform, Trim,
+    Watermark,
+};
 
 #[derive(Debug)]
-struct ICOConfigInner {
-    strip_metadata:      bool,
-    width:               u16,
-    height:              u16,
-    crop:                Option<Crop>,
-    shrink_only:         bool,
-    sharpen:             f64,
-    respect_orientation: bool,
+struct ICOConfigInner<'a> {
+    config: &'a ICOConfig,
+    width:  u16,
+    height: u16,
 }
 
-impl ICOConfigInner {
-    pub fn from(config: &ICOConfig) -> Vec<ICOConfigInner> {
+impl<'a> ICOConfigInner<'a> {
+    pub fn from(config: &'a ICOConfig) -> Vec<ICOConfigInner<'a>> {
         let mut output = Vec::new();
 
         for (width, height) in config.size.iter().copied() {
             output.push(ICOConfigInner {
-                strip_metadata: config.strip_metadata,
+                config,
                 width,
                 height,
-                crop: config.crop,
-                shrink_only: false,
-                sharpen: config.sharpen,
-                respect_orientation: config.respect_orientation,
             });
         }
 
@@ -34,19 +34,62 @@ impl ICOConfigInner {
     }
 }
 
+/// The encoding of the entries in an ICO image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ICOEncoding {
+    /// Encode entries whose width or height is at least 256 as PNG, and the others as BMP.
+    Auto,
+    /// Encode all entries as BMP.
+    BMP,
+    /// Encode all entries as PNG.
+    PNG,
+}
+
 #[derive(Debug)]
 /// The output config of an ICO image.
 pub struct ICOConfig {
     /// Remove the metadata stored in the input image.
     pub strip_metadata:      bool,
-    /// The size of the output image, made up of a width and a height. `0` means the original width or the original height.
+    /// The size of the output image, made up of a width and a height. `0` means the original width or the original height. An entry cannot be larger than 256x256, including the padding.
     pub size:                Vec<(u16, u16)>,
     /// Crop the image.
     pub crop:                Option<Crop>,
     /// The higher the sharper. A negative value means auto adjustment.
     pub sharpen:             f64,
-    /// Apply orientation from image metadata if available.
+    /// Apply orientation from image metadata if available, whether the image is read from a file, from data or from a `MagickWand` instance.
     pub respect_orientation: bool,
+    /// Restrict how the input image can be read.
+    pub read_policy:         Option<ReadPolicy>,
+    /// Limit the resources used to read the input image.
+    pub resource_limits:     Option<ResourceLimits>,
+    /// The encoding of the entries.
+    pub encoding:            ICOEncoding,
+    /// Pad non-square entries with transparent pixels to the requested size (or to a square if the width or the height is `0`).
+    pub padding:             bool,
+    /// The position of the hotspot relative to the size of each entry, from `(0.0, 0.0)` (top-left) to `(1.0, 1.0)` (bottom-right). Only used by `to_cur`.
+    pub hotspot:             (f64, f64),
+    /// Draw a watermark after resizing.
+    pub watermark:           Option<Watermark>,
+    /// Rotate and mirror the image before cropping and resizing.
+    pub transform:           Option<Transform>,
+    /// Adjust the tone and the colors after resizing.
+    pub adjustments:         Option<Adjustments>,
+    /// Apply effects in order after resizing.
+    pub effects:             Vec<Effect>,
+    /// Grade the colors with a 3D LUT after resizing.
+    pub lut:                 Option<Lut>,
+    /// Mask the image with a shape after resizing.
+    pub shape:               Option<Shape>,
+    /// Trim uniform borders or transparent edges before cropping and resizing.
+    pub trim:                Option<Trim>,
+    /// Make a color transparent before trimming, cropping and resizing.
+    pub chroma_key:          Option<ChromaKey>,
+    /// Make the pixels whose alpha is lower than this value (from 0 to 255) fully transparent, and the others fully opaque.
+    pub alpha_threshold:     Option<u8>,
+    /// How the image is resized.
+    pub resize_mode:         ResizeMode,
+    /// Compute a placeholder hash (BlurHash or ThumbHash) from the resized image.
+    pub placeholder:         Option<PlaceholderSink>,
 }
 
 impl ICOConfig {
@@ -58,6 +101,22 @@ impl ICOConfig {
     ///     crop: None,
     ///     sharpen: -1f64,
     ///     respect_orientation: false,
+    ///     read_policy: None,
+    ///     resource_limits: None,
+    ///     encoding: ICOEncoding::Auto,
+    ///     padding: true,
+    ///     hotspot: (0f64, 0f64),
+    ///     watermark: None,
+    ///     transform: None,
+    ///     adjustments: None,
+    ///     effects: Vec::new(),
+    ///     lut: None,
+    ///     shape: None,
+    ///     trim: None,
+    ///     chroma_key: None,
+    ///     alpha_threshold: None,
+    ///     resize_mode: ResizeMode::Resample,
+    ///     placeholder: None,
     /// }
     /// ```
     #[inline]
@@ -68,8 +127,45 @@ impl ICOConfig {
             crop:                None,
             sharpen:             -1f64,
             respect_orientation: false,
+            read_policy:         None,
+            resource_limits:     None,
+            encoding:            ICOEncoding::Auto,
+            padding:             true,
+            hotspot:             (0f64, 0f64),
+            watermark:           None,
+            transform:           None,
+            adjustments:         None,
+            effects:             Vec::new(),
+            lut:                 None,
+            shape:               None,
+            trim:                None,
+            chroma_key:          None,
+            alpha_threshold:     None,
+            resize_mode:         ResizeMode::Resample,
+            placeholder:         None,
         }
     }
+
+    /// Create a `ICOConfig` instance with the standard sizes of a favicon (16x16, 32x32 and 48x48).
+    #[inline]
+    pub fn favicon() -> ICOConfig {
+        let mut config = ICOConfig::new();
+
+        config.size = vec![(16, 16), (32, 32), (48, 48)];
+
+        config
+    }
+
+    /// Create a `ICOConfig` instance with the standard sizes of a Windows application icon (from 16x16 to 256x256).
+    #[inline]
+    pub fn windows() -> ICOConfig {
+        let mut config = ICOConfig::new();
+
+        config.size =
+            vec![(16, 16), (24, 24), (32, 32), (48, 48), (64, 64), (128, 128), (256, 256)];
+
+        config
+    }
 }
 
 impl Default for ICOConfig {
@@ -79,10 +175,10 @@ impl Default for ICOConfig {
     }
 }
 
-impl ImageConfig for ICOConfigInner {
+impl<'a> ImageConfig for ICOConfigInner<'a> {
     #[inline]
     fn is_strip_metadata(&self) -> bool {
-        self.strip_metadata
+        self.config.strip_metadata
     }
 
     #[inline]
@@ -97,64 +193,191 @@ impl ImageConfig for ICOConfigInner {
 
     #[inline]
     fn get_crop(&self) -> Option<Crop> {
-        self.crop
+        self.config.crop
     }
 
     #[inline]
     fn get_sharpen(&self) -> f64 {
-        self.sharpen
+        self.config.sharpen
     }
 
     #[inline]
     fn is_shrink_only(&self) -> bool {
-        self.shrink_only
+        false
     }
 
     #[inline]
     fn respect_orientation(&self) -> bool {
-        self.respect_orientation
+        self.config.respect_orientation
+    }
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&ReadPolicy> {
+        self.config.read_policy.as_ref()
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&ResourceLimits> {
+        self.config.resource_limits.as_ref()
+    }
+
+    #[inline]
+    fn get_watermark(&self) -> Option<&Watermark> {
+        self.config.watermark.as_ref()
+    }
+
+    #[inline]
+    fn get_transform(&self) -> Option<&Transform> {
+        self.config.transform.as_ref()
+    }
+
+    #[inline]
+    fn get_adjustments(&self) -> Option<&Adjustments> {
+        self.config.adjustments.as_ref()
+    }
+
+    #[inline]
+    fn get_effects(&self) -> &[Effect] {
+        &self.config.effects
+    }
+
+    #[inline]
+    fn get_lut(&self) -> Option<&Lut> {
+        self.config.lut.as_ref()
+    }
+
+    #[inline]
+    fn get_shape(&self) -> Option<&Shape> {
+        self.config.shape.as_ref()
+    }
+
+    #[inline]
+    fn get_trim(&self) -> Option<&Trim> {
+        self.config.trim.as_ref()
+    }
+
+    #[inline]
+    fn get_chroma_key(&self) -> Option<&ChromaKey> {
+        self.config.chroma_key.as_ref()
+    }
+
+    #[inline]
+    fn get_alpha_threshold(&self) -> Option<u8> {
+        self.config.alpha_threshold
+    }
+
+    #[inline]
+    fn get_resize_mode(&self) -> ResizeMode {
+        self.config.resize_mode
+    }
+
+    #[inline]
+    fn get_placeholder(&self) -> Option<&PlaceholderSink> {
+        self.config.placeholder.as_ref()
     }
 }
 
-/// Convert an image to an ICO image.
-pub fn to_ico(
-    output: &mut ImageResource,
-    input: &ImageResource,
-    config: &ICOConfig,
+fn add_icon_entry(
+    icon_dir: &mut ico::IconDir,
+    mut mw: MagickWand,
+    config: &ICOConfigInner,
+    resize: bool,
+    watermarks: &mut WatermarkCache,
 ) -> Result<(), MagickError> {
-    let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+    if resize {
+        let (width, height, sharpen) = compute_output_size_sharpen(&mw, config);
+
+        handle_resize(&mw, config, width, height)?;
+
+        mw.sharpen_image(0f64, sharpen)?;
+    }
+
+    handle_post_resize(&mut mw, config, watermarks)?;
+
+    let mut width = mw.get_image_width();
+    let mut height = mw.get_image_height();
+
+    if config.config.padding && width != height {
+        let (target_width, target_height) = if config.width == 0 || config.height == 0 {
+            let size = width.max(height);
+
+            (size, size)
+        } else {
+            ((config.width as usize).max(width), (config.height as usize).max(height))
+        };
+
+        extend_image_center(&mw, target_width, target_height)?;
+
+        width = target_width;
+        height = target_height;
+    }
+
+    if width > 256 || height > 256 {
+        return Err("An ICO entry cannot be larger than 256x256.".into());
+    }
+
+    mw.set_image_format("RGBA")?;
+    mw.set_image_depth(8)?;
+
+    let temp = mw.write_image_blob("RGBA")?;
+
+    let mut icon_image = ico::IconImage::from_rgba_data(width as u32, height as u32, temp);
+
+    if icon_dir.resource_type() == ico::ResourceType::Cursor {
+        let (x, y) = config.config.hotspot;
+
+        icon_image.set_cursor_hotspot(Some((
+            (x.clamp(0f64, 1f64) * (width - 1) as f64).round() as u16,
+            (y.clamp(0f64, 1f64) * (height - 1) as f64).round() as u16,
+        )));
+    }
+
+    let use_png = match config.config.encoding {
+        ICOEncoding::Auto => width >= 256 || height >= 256,
+        ICOEncoding::BMP => false,
+        ICOEncoding::PNG => true,
+    };
+
+    let entry = if use_png {
+        ico::IconDirEntry::encode_as_png(&icon_image)
+    } else {
+        ico::IconDirEntry::encode_as_bmp(&icon_image)
+    }
+    .map_err(|_| "Cannot encode the icon image.")?;
+
+    icon_dir.add_entry(entry);
+
+    Ok(())
+}
+
+fn to_icon_dir(
+    input: &(impl AsImageInput + ?Sized),
+    config: &ICOConfig,
+    resource_type: ico::ResourceType,
+) -> Result<ico::IconDir, MagickError> {
+    let mut icon_dir = ico::IconDir::new(resource_type);
 
     let ico_config_inner = ICOConfigInner::from(config);
 
     let mut config_iter = ico_config_inner.iter();
 
+    // the watermark is decoded only once for all the entries
+    let mut watermarks = WatermarkCache::new();
+
     let config = config_iter.next();
 
     if let Some(config) = config {
-        let (mut mw, vector) = fetch_magic_wand(input, config)?;
+        let (mw, vector) = fetch_magic_wand(input, config)?;
 
         if vector {
-            if config.strip_metadata {
+            if config.config.strip_metadata {
                 mw.strip_image()?;
             }
 
-            mw.set_image_format("RGBA")?;
-            mw.set_image_depth(8)?;
-
-            {
-                let temp = mw.write_image_blob("RGBA")?;
-
-                let icon_image = ico::IconImage::from_rgba_data(
-                    u32::from(config.width),
-                    u32::from(config.height),
-                    temp,
-                );
-
-                icon_dir.add_entry(ico::IconDirEntry::encode_as_bmp(&icon_image).unwrap());
-            }
+            add_icon_entry(&mut icon_dir, mw, config, false, &mut watermarks)?;
 
             for config in config_iter {
-                let (mut mw, vector) = fetch_magic_wand(input, config)?;
+                let (mw, vector) = fetch_magic_wand(input, config)?;
 
                 if !vector {
                     return Err("The input image may not be a correct vector.".into());
@@ -162,62 +385,92 @@ pub fn to_ico(
 
                 mw.strip_image()?;
 
-                mw.set_image_format("RGBA")?;
-                mw.set_image_depth(8)?;
-
-                let temp = mw.write_image_blob("RGBA")?;
-
-                let icon_image = ico::IconImage::from_rgba_data(
-                    u32::from(config.width),
-                    u32::from(config.height),
-                    temp,
-                );
-
-                icon_dir.add_entry(ico::IconDirEntry::encode_as_bmp(&icon_image).unwrap());
+                add_icon_entry(&mut icon_dir, mw, config, false, &mut watermarks)?;
             }
         } else {
             mw.strip_image()?;
 
-            mw.set_image_format("RGBA")?;
-            mw.set_image_depth(8)?;
+            add_icon_entry(&mut icon_dir, mw.clone(), config, true, &mut watermarks)?;
 
-            {
-                let (width, height, sharpen) = compute_output_size_sharpen(&mw, config);
+            for config in config_iter {
+                add_icon_entry(&mut icon_dir, mw.clone(), config, true, &mut watermarks)?;
+            }
+        }
+    }
 
-                mw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+    Ok(icon_dir)
+}
 
-                mw.sharpen_image(0f64, sharpen)?;
+fn to_icon_dir_from_sources(
+    inputs: &[(&ImageResource, (u16, u16))],
+    config: &ICOConfig,
+    resource_type: ico::ResourceType,
+) -> Result<ico::IconDir, MagickError> {
+    let largest = match inputs
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, (width, height)))| u32::from(*width) * u32::from(*height))
+    {
+        Some((index, _)) => index,
+        None => return Err("There is no input image.".into()),
+    };
+
+    let mut sizes = config.size.clone();
+
+    for (_, size) in inputs {
+        if !sizes.contains(size) {
+            sizes.push(*size);
+        }
+    }
 
-                let temp = mw.write_image_blob("RGBA")?;
+    // each raster source is decoded only once, and a vector source is rasterized at each size
+    let mut decoded: Vec<Option<MagickWand>> = inputs.iter().map(|_| None).collect();
 
-                let icon_image =
-                    ico::IconImage::from_rgba_data(u32::from(width), u32::from(height), temp);
+    let mut icon_dir = ico::IconDir::new(resource_type);
 
-                icon_dir.add_entry(ico::IconDirEntry::encode_as_bmp(&icon_image).unwrap());
-            }
+    // the watermark is decoded only once for all the entries
+    let mut watermarks = WatermarkCache::new();
 
-            for config in config_iter {
-                let mw = mw.clone();
+    for (width, height) in sizes {
+        let index = inputs.iter().position(|(_, size)| *size == (width, height)).unwrap_or(largest);
 
-                let (width, height, sharpen) = compute_output_size_sharpen(&mw, config);
+        let config = ICOConfigInner {
+            config,
+            width,
+            height,
+        };
 
-                mw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+        let (mw, resize) = match decoded[index].as_ref() {
+            Some(mw) => (mw.clone(), true),
+            None => {
+                let (mw, vector) = fetch_magic_wand(inputs[index].0, &config)?;
 
-                mw.sharpen_image(0f64, sharpen)?;
+                mw.strip_image()?;
 
-                let temp = mw.write_image_blob("RGBA")?;
+                if !vector {
+                    decoded[index] = Some(mw.clone());
+                }
 
-                let icon_image =
-                    ico::IconImage::from_rgba_data(u32::from(width), u32::from(height), temp);
+                (mw, !vector)
+            },
+        };
 
-                icon_dir.add_entry(ico::IconDirEntry::encode_as_bmp(&icon_image).unwrap());
-            }
-        }
+        add_icon_entry(&mut icon_dir, mw, &config, resize, &mut watermarks)?;
     }
 
+    Ok(icon_dir)
+}
+
+fn write_icon_dir(output: &mut ImageResource, icon_dir: ico::IconDir) -> Result<(), MagickError> {
+    let cursor = icon_dir.resource_type() == ico::ResourceType::Cursor;
+
     match output {
         ImageResource::Path(p) => {
-            if !p.ends_with_ignore_ascii_case_with_lowercase(".ico") {
+            if cursor {
+                if !p.ends_with_ignore_ascii_case_with_lowercase(".cur") {
+                    return Err("The file extension name is not cur.".into());
+                }
+            } else if !p.ends_with_ignore_ascii_case_with_lowercase(".ico") {
                 return Err("The file extension name is not ico.".into());
             }
 
@@ -231,10 +484,91 @@ pub fn to_ico(
         ImageResource::Data(b) => {
             icon_dir.write(b).map_err(|_| "Cannot convert to icon data.")?;
         },
+        ImageResource::Writer(w) => {
+            icon_dir
+                .write(&mut *w)
+                .and_then(|_| w.flush())
+                .map_err(|_| "Cannot write the icon data.")?;
+        },
         ImageResource::MagickWand(_) => {
+            if cursor {
+                return Err("CUR cannot be output to a MagickWand instance.".into());
+            }
+
             return Err("ICO cannot be output to a MagickWand instance.".into());
         },
     }
 
     Ok(())
 }
+
+/// Convert an image to an ICO image.
+pub fn to_ico(
+    output: &mut ImageResource,
+    input: &(impl AsImageInput + ?Sized),
+    config: &ICOConfig,
+) -> Result<(), MagickError> {
+    let _operation = begin_operation();
+
+    let icon_dir = to_icon_dir(input, config, ico::ResourceType::Icon)?;
+
+    write_icon_dir(output, icon_dir)
+}
+
+/// Convert an image to a CUR image (a cursor). The hotspot is set by `ICOConfig::hotspot`.
+pub fn to_cur(
+    output: &mut ImageResource,
+    input: &(impl AsImageInput + ?Sized),
+    config: &ICOConfig,
+) -> Result<(), MagickError> {
+    let _operation = begin_operation();
+
+    let icon_dir = to_icon_dir(input, config, ico::ResourceType::Cursor)?;
+
+    write_icon_dir(output, icon_dir)
+}
+
+/// Convert images to an ICO image, using a different source image for each size. The sizes of the output image are the sizes in `config.size` and the sizes of the sources. Sizes without their own source are resized from the largest source.
+pub fn to_ico_from_sources(
+    output: &mut ImageResource,
+    inputs: &[(&ImageResource, (u16, u16))],
+    config: &ICOConfig,
+) -> Result<(), MagickError> {
+    let _operation = begin_operation();
+
+    let icon_dir = to_icon_dir_from_sources(inputs, config, ico::ResourceType::Icon)?;
+
+    write_icon_dir(output, icon_dir)
+}
+
+/// Extract each entry of an ICO (or CUR) image as a PNG image.
+pub fn from_ico(input: &(impl AsImageInput + ?Sized)) -> Result<Vec<ImageResource>, MagickError> {
+    let _operation = begin_operation();
+
+    let icon_dir = match input.as_image_input()? {
+        ImageInput::Path(p) => {
+            let file = std::fs::File::open(p).map_err(|_| "Cannot open the icon file.")?;
+
+            ico::IconDir::read(file)
+        },
+        ImageInput::Data(b) => ico::IconDir::read(std::io::Cursor::new(b)),
+        ImageInput::MagickWand(_) => {
+            return Err("ICO cannot be read from a MagickWand instance.".into());
+        },
+    }
+    .map_err(|_| "Cannot read the icon data.")?;
+
+    let mut outputs = Vec::with_capacity(icon_dir.entries().len());
+
+    for entry in icon_dir.entries() {
+        let icon_image = entry.decode().map_err(|_| "Cannot decode the icon image.")?;
+
+        let mut data = Vec::new();
+
+        icon_image.write_png(&mut data).map_err(|_| "Cannot convert the icon image to PNG.")?;
+
+        outputs.push(ImageResource::Data(data));
+    }
+
+    Ok(outputs)
+}
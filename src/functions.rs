@@ -1,12 +1,19 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fs};
 
-use magick_rust::{MagickError, MagickWand, OrientationType, PixelWand};
+use magick_rust::{
+    ColorspaceType, FilterType, MagickError, MagickWand, OrientationType, PixelWand,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
 use crate::{
-    image_config::compute_output_size_if_different, Crop, ImageConfig, ImageResource,
-    START_CALL_ONCE,
+    chroma_key::apply_alpha_threshold,
+    image_config::compute_output_size_if_different,
+    resource_limits::{check_deadline, finish_deadline, set_deadline},
+    runtime::{begin_operation, global_options},
+    watermark::{apply_watermark, WatermarkCache},
+    AsImageInput, ChromaKey, Crop, ImageConfig, ImageInput, ReadPolicy, ResizeMode, ResourceLimits,
+    Transform, Trim,
 };
 
 #[cfg(feature = "none-background")]
@@ -25,13 +32,36 @@ macro_rules! set_none_background {
 
 pub(crate) use set_none_background;
 
+#[cfg(feature = "tokio")]
+#[inline]
+fn check_cancelled() -> Result<(), MagickError> {
+    crate::asynchronous::check_current_job()
+}
+
+#[cfg(not(feature = "tokio"))]
+#[inline]
+fn check_cancelled() -> Result<(), MagickError> {
+    Ok(())
+}
+
+/// Check at a stage boundary whether a conversion should stop, because its deadline has passed or its async job has been cancelled.
+#[inline]
+fn check_stage(mw: &MagickWand) -> Result<(), MagickError> {
+    check_deadline(mw)?;
+
+    check_cancelled()
+}
+
+/// The error message used when an `ImageResource::Writer` is given as an input.
+pub(crate) const WRITER_AS_INPUT: &str = "A writer cannot be used as an input image.";
+
 static RE_SVG: Lazy<Regex> = Lazy::new(|| Regex::new("(?i)(<svg[\\s\\S]*?>)").unwrap());
 static RE_WIDTH: Lazy<Regex> =
     Lazy::new(|| Regex::new("(?i)([\\s\\S]*?[\\s]width[\\s]*=[\\s]*\"([\\s\\S]*?)\")").unwrap());
 static RE_HEIGHT: Lazy<Regex> =
     Lazy::new(|| Regex::new("(?i)([\\s\\S]*?[\\s]height[\\s]*=[\\s]*\"([\\s\\S]*?)\")").unwrap());
 
-fn handle_orientation(mw: &MagickWand) -> Result<(), MagickError> {
+pub(crate) fn handle_orientation(mw: &MagickWand) -> Result<(), MagickError> {
     let orientation = mw.get_image_orientation();
 
     match orientation {
@@ -72,106 +102,361 @@ fn handle_orientation(mw: &MagickWand) -> Result<(), MagickError> {
     Ok(())
 }
 
+/// Read (or ping) an image from a path, enforcing a read policy if it is set. The policy is checked before ImageMagick touches the image.
+pub(crate) fn read_image_from_path(
+    mw: &MagickWand,
+    p: &str,
+    policy: Option<&ReadPolicy>,
+    ping: bool,
+) -> Result<(), MagickError> {
+    match policy {
+        Some(policy) => {
+            let (p, format) = policy.check_path(p)?;
+
+            if ping {
+                mw.ping_image(p.as_str())?;
+            } else {
+                mw.read_image(p.as_str())?;
+            }
+
+            policy.check_identified(format, mw.get_image_format()?.as_str())
+        },
+        None => {
+            if ping {
+                mw.ping_image(p)
+            } else {
+                mw.read_image(p)
+            }
+        },
+    }
+}
+
+/// Read (or ping) an image from a blob, enforcing a read policy if it is set. The policy is checked before ImageMagick touches the image.
+pub(crate) fn read_image_from_blob(
+    mw: &mut MagickWand,
+    b: &[u8],
+    policy: Option<&ReadPolicy>,
+    ping: bool,
+) -> Result<(), MagickError> {
+    match policy {
+        Some(policy) => {
+            let format = policy.check_head(b)?;
+
+            // pin the coder so that ImageMagick cannot pick another one by itself
+            if let Some(format) = format {
+                mw.set_format(format)?;
+            }
+
+            if ping {
+                mw.ping_image_blob(b)?;
+            } else {
+                mw.read_image_blob(b)?;
+            }
+
+            policy.check_identified(format, mw.get_image_format()?.as_str())
+        },
+        None => {
+            if ping {
+                mw.ping_image_blob(b)
+            } else {
+                mw.read_image_blob(b)
+            }
+        },
+    }
+}
+
+/// Ping an image to check it against the resource limits (if they are set) before reading it.
+pub(crate) fn read_image_limited(
+    mw: &mut MagickWand,
+    limits: Option<&ResourceLimits>,
+    read: impl Fn(&mut MagickWand, bool) -> Result<(), MagickError>,
+) -> Result<(), MagickError> {
+    match limits {
+        Some(limits) => {
+            let mut pmw = MagickWand::new();
+
+            read(&mut pmw, true).map_err(|err| limits.map_error(err))?;
+
+            limits.check(&pmw)?;
+
+            read(mw, false).map_err(|err| limits.map_error(err))
+        },
+        None => read(mw, false),
+    }
+}
+
+/// How an image is prepared before resizing. The steps are applied in this order: orientation, transform, chroma key, trim and crop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Preparation<'a> {
+    pub(crate) respect_orientation: bool,
+    pub(crate) transform:           Option<&'a Transform>,
+    pub(crate) chroma_key:          Option<&'a ChromaKey>,
+    pub(crate) trim:                Option<&'a Trim>,
+    pub(crate) crop:                Option<Crop>,
+}
+
+impl<'a> Preparation<'a> {
+    #[inline]
+    pub(crate) fn of(config: &'a impl ImageConfig) -> Preparation<'a> {
+        Preparation {
+            respect_orientation: config.respect_orientation(),
+            transform:           config.get_transform(),
+            chroma_key:          config.get_chroma_key(),
+            trim:                config.get_trim(),
+            crop:                config.get_crop(),
+        }
+    }
+
+    /// Prepare a decoded image.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        if self.respect_orientation {
+            handle_orientation(mw)?;
+        }
+
+        if let Some(transform) = self.transform {
+            transform.apply(mw)?;
+        }
+
+        if let Some(chroma_key) = self.chroma_key {
+            chroma_key.apply(mw)?;
+        }
+
+        if let Some(trim) = self.trim {
+            trim.apply(mw)?;
+        }
+
+        if let Some(crop) = self.crop {
+            handle_crop(mw, crop)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[inline]
 pub fn fetch_magic_wand(
-    input: &ImageResource,
+    input: &(impl AsImageInput + ?Sized),
     config: &impl ImageConfig,
 ) -> Result<(MagickWand, bool), MagickError> {
-    START_CALL_ONCE();
+    fetch_magic_wand_with_trust(input, config, false)
+}
 
-    match input {
-        ImageResource::Path(p) => {
-            let mw = MagickWand::new();
+/// Fetch a trusted auxiliary image (such as a watermark, a layer or a Hald CLUT image), which is supplied by the application instead of a user, so it is not checked by the read policy of the `ImageConvert` runtime.
+#[inline]
+pub(crate) fn fetch_trusted_magic_wand(
+    input: &(impl AsImageInput + ?Sized),
+    config: &impl ImageConfig,
+) -> Result<(MagickWand, bool), MagickError> {
+    fetch_magic_wand_with_trust(input, config, true)
+}
 
-            set_none_background!(mw);
+fn fetch_magic_wand_with_trust(
+    input: &(impl AsImageInput + ?Sized),
+    config: &impl ImageConfig,
+    trusted: bool,
+) -> Result<(MagickWand, bool), MagickError> {
+    let _operation = begin_operation();
+
+    // fall back to the options of the `ImageConvert` runtime
+    let global = global_options();
+    let policy = match config.get_read_policy() {
+        Some(policy) => Some(policy),
+        None if trusted => None,
+        None => global.as_deref().and_then(|o| o.read_policy.as_ref()),
+    };
+    let limits =
+        config.get_resource_limits().or(global.as_deref().and_then(|o| o.resource_limits.as_ref()));
+    let deadline = limits.and_then(ResourceLimits::deadline);
 
-            mw.read_image(p.as_str())?;
+    // a cancelled job should not decode its input at all
+    check_cancelled()?;
 
-            if config.respect_orientation() {
-                handle_orientation(&mw)?;
-            }
+    let input = input.as_image_input()?;
 
-            if let Some(crop) = config.get_crop() {
-                handle_crop(&mw, crop)?;
-            }
+    let mut mw = match input {
+        ImageInput::Path(p) => {
+            let mut mw = MagickWand::new();
 
-            let format = mw.get_image_format()?;
-
-            match format.as_str() {
-                "SVG" | "MVG" => {
-                    match compute_output_size_if_different(&mw, config) {
-                        Some((new_width, new_height)) => {
-                            let original_width = mw.get_image_width() as u16;
-
-                            if new_width < original_width {
-                                // TODO ImageMagick handles the smaller size of SVG poorly, so just do resize
-                                Ok((mw, false))
-                            } else {
-                                use std::fs;
-
-                                match fs::read_to_string(p) {
-                                    Ok(svg) => {
-                                        fetch_magic_wand_inner(mw, new_width, new_height, svg)
-                                    },
-                                    Err(_) => Ok((mw, false)),
-                                }
-                            }
-                        },
-                        None => Ok((mw, true)),
-                    }
-                },
-                _ => Ok((mw, false)),
-            }
+            set_none_background!(mw);
+
+            read_image_limited(&mut mw, limits, |mw, ping| {
+                read_image_from_path(mw, p, policy, ping)
+            })?;
+
+            mw
         },
-        ImageResource::Data(b) => {
-            let mw = MagickWand::new();
+        ImageInput::Data(b) => {
+            let mut mw = MagickWand::new();
 
             set_none_background!(mw);
 
-            mw.read_image_blob(b)?;
+            read_image_limited(&mut mw, limits, |mw, ping| {
+                read_image_from_blob(mw, b, policy, ping)
+            })?;
+
+            mw
+        },
+        ImageInput::MagickWand(mw) => mw.clone(),
+    };
+
+    if let Some(deadline) = deadline {
+        set_deadline(&mut mw, deadline)?;
+    }
+
+    let preparation = Preparation::of(config);
+
+    preparation.apply(&mut mw)?;
+
+    if let ImageInput::MagickWand(_) = input {
+        return Ok((mw, false));
+    }
+
+    let format = mw.get_image_format()?;
 
-            if let Some(crop) = config.get_crop() {
-                handle_crop(&mw, crop)?;
+    match format.as_str() {
+        "SVG" | "MVG" => {
+            // seam carving needs the raster image to be resized, so it cannot be re-rendered
+            if is_content_aware(config) {
+                return Ok((mw, false));
             }
 
-            let format = mw.get_image_format()?;
-
-            match format.as_str() {
-                "SVG" | "MVG" => {
-                    match compute_output_size_if_different(&mw, config) {
-                        Some((new_width, new_height)) => {
-                            let original_width = mw.get_image_width() as u16;
-
-                            if new_width < original_width {
-                                // TODO ImageMagick handles the smaller size of SVG poorly, so just do resize
-                                Ok((mw, false))
-                            } else {
-                                match String::from_utf8(b.to_vec()) {
-                                    Ok(svg) => {
-                                        fetch_magic_wand_inner(mw, new_width, new_height, svg)
-                                    },
-                                    Err(_) => Ok((mw, false)),
-                                }
-                            }
+            match compute_output_size_if_different(&mw, config) {
+                Some((new_width, new_height)) => {
+                    let original_width = mw.get_image_width() as u16;
+
+                    if new_width < original_width {
+                        // TODO ImageMagick handles the smaller size of SVG poorly, so just do resize
+                        return Ok((mw, false));
+                    }
+
+                    let svg = match input {
+                        ImageInput::Path(p) => fs::read_to_string(p).ok(),
+                        ImageInput::Data(b) => String::from_utf8(b.to_vec()).ok(),
+                        ImageInput::MagickWand(_) => None,
+                    };
+
+                    match svg {
+                        Some(svg) => {
+                            fetch_magic_wand_vector(mw, new_width, new_height, svg, &preparation)
                         },
-                        None => Ok((mw, true)),
+                        None => Ok((mw, false)),
                     }
                 },
-                _ => Ok((mw, false)),
+                None => Ok((mw, true)),
             }
         },
-        ImageResource::MagickWand(mw) => {
-            let mw = mw.clone();
+        _ => Ok((mw, false)),
+    }
+}
 
-            if let Some(crop) = config.get_crop() {
-                handle_crop(&mw, crop)?;
-            }
+/// Whether an image is resized by seam carving, which only happens if both the width and the height are set.
+#[inline]
+pub(crate) fn is_content_aware(config: &impl ImageConfig) -> bool {
+    matches!(config.get_resize_mode(), ResizeMode::ContentAware { .. })
+        && config.get_width() > 0
+        && config.get_height() > 0
+}
+
+/// Resize an image to the size computed from the config, in the resize mode of the config.
+pub(crate) fn handle_resize(
+    mw: &MagickWand,
+    config: &impl ImageConfig,
+    width: u16,
+    height: u16,
+) -> Result<(), MagickError> {
+    check_stage(mw)?;
+
+    let fallback = match config.get_resize_mode() {
+        ResizeMode::ContentAware {
+            fallback,
+        } if config.get_width() > 0 && config.get_height() > 0 => fallback,
+        _ => return mw.resize_image(width as usize, height as usize, FilterType::Lanczos),
+    };
+
+    let original_width = mw.get_image_width();
+    let original_height = mw.get_image_height();
+
+    let mut target_width = config.get_width() as usize;
+    let mut target_height = config.get_height() as usize;
+
+    if config.is_shrink_only() {
+        target_width = target_width.min(original_width);
+        target_height = target_height.min(original_height);
+    }
 
-            Ok((mw, false))
+    // resample the image to cover the target size first, so that only the extra part is carved
+    let scale = (target_width as f64 / original_width as f64)
+        .max(target_height as f64 / original_height as f64);
+
+    let cover_width = ((original_width as f64 * scale).round() as usize).max(target_width);
+    let cover_height = ((original_height as f64 * scale).round() as usize).max(target_height);
+
+    if cover_width != original_width || cover_height != original_height {
+        mw.resize_image(cover_width, cover_height, FilterType::Lanczos)?;
+    }
+
+    if cover_width == target_width && cover_height == target_height {
+        return Ok(());
+    }
+
+    match mw.liquid_rescale_image(target_width, target_height, 1f64, 0f64) {
+        Ok(_) => Ok(()),
+        Err(_) if fallback => mw.crop_image(
+            target_width,
+            target_height,
+            ((cover_width - target_width) / 2) as isize,
+            ((cover_height - target_height) / 2) as isize,
+        ),
+        Err(_) => {
+            Err("Content-aware resizing needs ImageMagick built with the liblqr delegate.".into())
         },
     }
 }
 
-fn handle_crop(mw: &MagickWand, crop: Crop) -> Result<(), MagickError> {
+/// Process an image after it is resized.
+pub(crate) fn handle_post_resize(
+    mw: &mut MagickWand,
+    config: &impl ImageConfig,
+    watermarks: &mut WatermarkCache,
+) -> Result<(), MagickError> {
+    check_stage(mw)?;
+
+    if let Some(adjustments) = config.get_adjustments() {
+        adjustments.apply(mw)?;
+    }
+
+    if let Some(lut) = config.get_lut() {
+        lut.apply(mw)?;
+    }
+
+    for effect in config.get_effects() {
+        effect.apply(mw)?;
+    }
+
+    if let Some(watermark) = config.get_watermark() {
+        check_stage(mw)?;
+
+        apply_watermark(mw, watermark, watermarks)?;
+    }
+
+    if let Some(shape) = config.get_shape() {
+        shape.apply(mw)?;
+    }
+
+    if let Some(threshold) = config.get_alpha_threshold() {
+        apply_alpha_threshold(mw, threshold)?;
+    }
+
+    if let Some(placeholder) = config.get_placeholder() {
+        placeholder.compute(mw)?;
+    }
+
+    finish_deadline(mw)?;
+
+    check_cancelled()
+}
+
+pub(crate) fn handle_crop(mw: &MagickWand, crop: Crop) -> Result<(), MagickError> {
     match crop {
         Crop::Center(w, h) => {
             let r = w / h;
@@ -204,7 +489,111 @@ fn handle_crop(mw: &MagickWand, crop: Crop) -> Result<(), MagickError> {
     Ok(())
 }
 
-fn fetch_magic_wand_inner(
+/// Extend the canvas of an image to a larger size with transparent pixels, keeping the image in the center.
+pub(crate) fn extend_image_center(
+    mw: &MagickWand,
+    width: usize,
+    height: usize,
+) -> Result<(), MagickError> {
+    let original_width = mw.get_image_width();
+    let original_height = mw.get_image_height();
+
+    let mut pw = PixelWand::new();
+    pw.set_color("none")?;
+    mw.set_image_background_color(&pw)?;
+
+    mw.extend_image(
+        width,
+        height,
+        -((width.saturating_sub(original_width) / 2) as isize),
+        -((height.saturating_sub(original_height) / 2) as isize),
+    )
+}
+
+/// Parse a color, such as `"#FF8000"` or `"orange"`, into 8-bit RGB samples.
+pub(crate) fn parse_color(color: &str) -> Result<[u8; 3], MagickError> {
+    let mut pw = PixelWand::new();
+    pw.set_color(color)?;
+
+    let mw = MagickWand::new();
+
+    mw.new_image(1, 1, &pw)?;
+
+    match mw.export_image_pixels(0, 0, 1, 1, "RGB") {
+        Some(pixels) if pixels.len() == 3 => Ok([pixels[0], pixels[1], pixels[2]]),
+        _ => Err("Cannot parse the color.".into()),
+    }
+}
+
+/// Modify the RGB samples of an image, which are from 0 to 1 and are not quantized to 8 bits. An image in another colorspace (such as CMYK or grayscale) is converted to sRGB first. The alpha channel is kept as it is.
+pub(crate) fn map_pixels(
+    mw: &mut MagickWand,
+    f: impl FnOnce(&mut [f64], usize, usize),
+) -> Result<(), MagickError> {
+    if !matches!(mw.get_image_colorspace(), ColorspaceType::sRGB | ColorspaceType::RGB) {
+        mw.transform_image_colorspace(ColorspaceType::sRGB)?;
+    }
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let mut pixels = match mw.export_image_pixels_double(0, 0, width, height, "RGB") {
+        Some(pixels) => pixels,
+        None => return Err("Cannot export the pixels of the image.".into()),
+    };
+
+    f(&mut pixels, width, height);
+
+    mw.import_image_pixels_double(0, 0, width, height, &pixels, "RGB")
+}
+
+/// Re-render a vector image at a new size, and transform and key it again if it is re-rendered. A trimmed or cropped image is not re-rendered.
+pub(crate) fn fetch_magic_wand_vector(
+    mw: MagickWand,
+    new_width: u16,
+    new_height: u16,
+    svg: String,
+    preparation: &Preparation,
+) -> Result<(MagickWand, bool), MagickError> {
+    // the size of a trimmed or cropped image is not the size of the whole vector image, so just do resize
+    if preparation.trim.is_some() || preparation.crop.is_some() {
+        return Ok((mw, false));
+    }
+
+    let (mut mw, vector) = match preparation.transform {
+        Some(transform) => {
+            // the size of an image rotated by an arbitrary angle cannot be mapped back, so just do resize
+            if !transform.is_right_angle() {
+                return Ok((mw, false));
+            }
+
+            let (new_width, new_height) = if transform.swaps_dimensions() {
+                (new_height, new_width)
+            } else {
+                (new_width, new_height)
+            };
+
+            let (mw, vector) = fetch_magic_wand_inner(mw, new_width, new_height, svg)?;
+
+            if vector {
+                transform.apply(&mw)?;
+            }
+
+            (mw, vector)
+        },
+        None => fetch_magic_wand_inner(mw, new_width, new_height, svg)?,
+    };
+
+    if vector {
+        if let Some(chroma_key) = preparation.chroma_key {
+            chroma_key.apply(&mut mw)?;
+        }
+    }
+
+    Ok((mw, vector))
+}
+
+pub(crate) fn fetch_magic_wand_inner(
     mw: MagickWand,
     new_width: u16,
     new_height: u16,
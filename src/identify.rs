@@ -1,6 +1,12 @@
 use magick_rust::{MagickError, MagickWand};
 
-use crate::{functions::set_none_background, ImageResource, InterlaceType, START_CALL_ONCE};
+use crate::{
+    functions::{
+        read_image_from_blob, read_image_from_path, read_image_limited, set_none_background,
+    },
+    runtime::{begin_operation, global_options},
+    AsImageInput, ImageInput, InterlaceType, ReadPolicy,
+};
 
 /// The resolution of an image.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,29 +53,69 @@ fn identify_inner(mw: &MagickWand) -> Result<ImageIdentify, MagickError> {
 }
 
 /// Ping and identify an image.
-pub fn identify_ping(input: &ImageResource) -> Result<ImageIdentify, MagickError> {
-    START_CALL_ONCE();
+#[inline]
+pub fn identify_ping(input: &(impl AsImageInput + ?Sized)) -> Result<ImageIdentify, MagickError> {
+    identify_ping_inner(input, None)
+}
+
+/// Ping and identify an image, enforcing a read policy.
+#[inline]
+pub fn identify_ping_with_policy(
+    input: &(impl AsImageInput + ?Sized),
+    policy: &ReadPolicy,
+) -> Result<ImageIdentify, MagickError> {
+    identify_ping_inner(input, Some(policy))
+}
+
+/// Read and identify an image. It can read an image as `MagickWand` instances.
+#[inline]
+pub fn identify_read(
+    output: &mut Option<MagickWand>,
+    input: &(impl AsImageInput + ?Sized),
+) -> Result<ImageIdentify, MagickError> {
+    identify_read_inner(output, input, None)
+}
+
+/// Read and identify an image, enforcing a read policy. It can read an image as `MagickWand` instances.
+#[inline]
+pub fn identify_read_with_policy(
+    output: &mut Option<MagickWand>,
+    input: &(impl AsImageInput + ?Sized),
+    policy: &ReadPolicy,
+) -> Result<ImageIdentify, MagickError> {
+    identify_read_inner(output, input, Some(policy))
+}
 
-    match input {
-        ImageResource::Path(p) => {
+fn identify_ping_inner(
+    input: &(impl AsImageInput + ?Sized),
+    policy: Option<&ReadPolicy>,
+) -> Result<ImageIdentify, MagickError> {
+    let _operation = begin_operation();
+
+    // fall back to the read policy of the `ImageConvert` runtime
+    let global = global_options();
+    let policy = policy.or(global.as_deref().and_then(|o| o.read_policy.as_ref()));
+
+    match input.as_image_input()? {
+        ImageInput::Path(p) => {
             let mw = MagickWand::new();
 
-            mw.ping_image(p.as_str())?;
+            read_image_from_path(&mw, p, policy, true)?;
 
             let identify = identify_inner(&mw)?;
 
             Ok(identify)
         },
-        ImageResource::Data(b) => {
-            let mw = MagickWand::new();
+        ImageInput::Data(b) => {
+            let mut mw = MagickWand::new();
 
-            mw.ping_image_blob(b)?;
+            read_image_from_blob(&mut mw, b, policy, true)?;
 
             let identify = identify_inner(&mw)?;
 
             Ok(identify)
         },
-        ImageResource::MagickWand(mw) => {
+        ImageInput::MagickWand(mw) => {
             let identify = identify_inner(mw)?;
 
             Ok(identify)
@@ -77,46 +123,55 @@ pub fn identify_ping(input: &ImageResource) -> Result<ImageIdentify, MagickError
     }
 }
 
-/// Read and identify an image. It can read an image as `MagickWand` instances.
-pub fn identify_read(
+fn identify_read_inner(
     output: &mut Option<MagickWand>,
-    input: &ImageResource,
+    input: &(impl AsImageInput + ?Sized),
+    policy: Option<&ReadPolicy>,
 ) -> Result<ImageIdentify, MagickError> {
-    START_CALL_ONCE();
+    let mw = read_magic_wand(input, policy)?;
 
-    match input {
-        ImageResource::Path(p) => {
-            let mw = MagickWand::new();
+    let identify = identify_inner(&mw)?;
 
-            set_none_background!(mw);
+    output.replace(mw);
 
-            mw.read_image(p.as_str())?;
+    Ok(identify)
+}
 
-            let identify = identify_inner(&mw)?;
+/// Read an image as a `MagickWand` instance, falling back to the read policy and the resource limits of the `ImageConvert` runtime.
+pub(crate) fn read_magic_wand(
+    input: &(impl AsImageInput + ?Sized),
+    policy: Option<&ReadPolicy>,
+) -> Result<MagickWand, MagickError> {
+    let _operation = begin_operation();
 
-            output.replace(mw);
+    // fall back to the options of the `ImageConvert` runtime
+    let global = global_options();
+    let policy = policy.or(global.as_deref().and_then(|o| o.read_policy.as_ref()));
+    let limits = global.as_deref().and_then(|o| o.resource_limits.as_ref());
 
-            Ok(identify)
-        },
-        ImageResource::Data(b) => {
-            let mw = MagickWand::new();
+    match input.as_image_input()? {
+        ImageInput::Path(p) => {
+            let mut mw = MagickWand::new();
 
             set_none_background!(mw);
 
-            mw.read_image_blob(b)?;
+            read_image_limited(&mut mw, limits, |mw, ping| {
+                read_image_from_path(mw, p, policy, ping)
+            })?;
 
-            let identify = identify_inner(&mw)?;
-
-            output.replace(mw);
-
-            Ok(identify)
+            Ok(mw)
         },
-        ImageResource::MagickWand(mw) => {
-            let identify = identify_inner(mw)?;
+        ImageInput::Data(b) => {
+            let mut mw = MagickWand::new();
+
+            set_none_background!(mw);
 
-            output.replace(mw.clone());
+            read_image_limited(&mut mw, limits, |mw, ping| {
+                read_image_from_blob(mw, b, policy, ping)
+            })?;
 
-            Ok(identify)
+            Ok(mw)
         },
+        ImageInput::MagickWand(mw) => Ok(mw.clone()),
     }
 }
@@ -2,7 +2,10 @@ use std::fmt::Debug;
 
 use magick_rust::MagickWand;
 
-use crate::Crop;
+use crate::{
+    Adjustments, ChromaKey, Crop, Effect, Lut, PlaceholderSink, ReadPolicy, ResizeMode,
+    ResourceLimits, Shape, Transform, Trim, Watermark,
+};
 
 // The general config of an image format.
 pub trait ImageConfig: Debug {
@@ -13,6 +16,116 @@ pub trait ImageConfig: Debug {
     fn get_sharpen(&self) -> f64;
     fn is_shrink_only(&self) -> bool;
     fn respect_orientation(&self) -> bool;
+
+    #[inline]
+    fn get_read_policy(&self) -> Option<&ReadPolicy> {
+        None
+    }
+
+    #[inline]
+    fn get_resource_limits(&self) -> Option<&ResourceLimits> {
+        None
+    }
+
+    #[inline]
+    fn get_watermark(&self) -> Option<&Watermark> {
+        None
+    }
+
+    #[inline]
+    fn get_transform(&self) -> Option<&Transform> {
+        None
+    }
+
+    #[inline]
+    fn get_adjustments(&self) -> Option<&Adjustments> {
+        None
+    }
+
+    #[inline]
+    fn get_effects(&self) -> &[Effect] {
+        &[]
+    }
+
+    #[inline]
+    fn get_lut(&self) -> Option<&Lut> {
+        None
+    }
+
+    #[inline]
+    fn get_shape(&self) -> Option<&Shape> {
+        None
+    }
+
+    #[inline]
+    fn get_trim(&self) -> Option<&Trim> {
+        None
+    }
+
+    #[inline]
+    fn get_chroma_key(&self) -> Option<&ChromaKey> {
+        None
+    }
+
+    #[inline]
+    fn get_alpha_threshold(&self) -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    fn get_resize_mode(&self) -> ResizeMode {
+        ResizeMode::Resample
+    }
+
+    #[inline]
+    fn get_placeholder(&self) -> Option<&PlaceholderSink> {
+        None
+    }
+}
+
+/// The config used to fetch an auxiliary image (such as a watermark or a layer) at the size it is drawn. A vector image is rasterized at that size.
+#[derive(Debug)]
+pub(crate) struct ResizeConfig {
+    pub(crate) width:  u16,
+    pub(crate) height: u16,
+    pub(crate) crop:   Option<Crop>,
+}
+
+impl ImageConfig for ResizeConfig {
+    #[inline]
+    fn is_strip_metadata(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    #[inline]
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    #[inline]
+    fn get_crop(&self) -> Option<Crop> {
+        self.crop
+    }
+
+    #[inline]
+    fn get_sharpen(&self) -> f64 {
+        0f64
+    }
+
+    #[inline]
+    fn is_shrink_only(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn respect_orientation(&self) -> bool {
+        true
+    }
 }
 
 // Compute an appropriate sharpen value for the resized image.
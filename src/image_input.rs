@@ -0,0 +1,110 @@
+use magick_rust::{MagickError, MagickWand};
+
+use crate::{functions::WRITER_AS_INPUT, ImageResource};
+
+/// A borrowed view of an input image. Image data is read from the borrowed slice without being copied.
+#[derive(Debug, Clone, Copy)]
+pub enum ImageInput<'a> {
+    Path(&'a str),
+    Data(&'a [u8]),
+    MagickWand(&'a MagickWand),
+}
+
+/// Types which can be used as an input image.
+pub trait AsImageInput {
+    /// Borrow this instance as an `ImageInput`.
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError>;
+}
+
+impl AsImageInput for ImageResource {
+    #[inline]
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+        match self {
+            ImageResource::Path(p) => Ok(ImageInput::Path(p.as_str())),
+            ImageResource::Data(b) => Ok(ImageInput::Data(b.as_slice())),
+            ImageResource::MagickWand(mw) => Ok(ImageInput::MagickWand(mw)),
+            ImageResource::Writer(_) => Err(WRITER_AS_INPUT.into()),
+        }
+    }
+}
+
+impl<'a> AsImageInput for ImageInput<'a> {
+    #[inline]
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+        Ok(*self)
+    }
+}
+
+impl AsImageInput for [u8] {
+    #[inline]
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+        Ok(ImageInput::Data(self))
+    }
+}
+
+impl AsImageInput for Vec<u8> {
+    #[inline]
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+        Ok(ImageInput::Data(self.as_slice()))
+    }
+}
+
+impl AsImageInput for MagickWand {
+    #[inline]
+    fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+        Ok(ImageInput::MagickWand(self))
+    }
+}
+
+#[cfg(feature = "mmap")]
+mod mapped_image {
+    use std::{fs::File, io, ops::Deref, path::Path};
+
+    use magick_rust::MagickError;
+    use memmap2::Mmap;
+
+    use super::{AsImageInput, ImageInput};
+
+    /// An input image file mapped into memory, so that it is not read into a buffer.
+    #[derive(Debug)]
+    pub struct MappedImage {
+        mmap: Mmap,
+    }
+
+    impl MappedImage {
+        /// Map an image file into memory.
+        ///
+        /// # Safety
+        ///
+        /// The file must not be modified or truncated (by this process or any other process) while the returned `MappedImage` instance is alive. Otherwise, the mapped bytes can change or become inaccessible while they are being read, which is undefined behavior.
+        pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<MappedImage, io::Error> {
+            let file = File::open(path)?;
+
+            // SAFETY: the caller guarantees that the file is not modified or truncated while it is mapped
+            let mmap = unsafe { Mmap::map(&file)? };
+
+            Ok(MappedImage {
+                mmap,
+            })
+        }
+    }
+
+    impl Deref for MappedImage {
+        type Target = [u8];
+
+        #[inline]
+        fn deref(&self) -> &[u8] {
+            &self.mmap
+        }
+    }
+
+    impl AsImageInput for MappedImage {
+        #[inline]
+        fn as_image_input(&self) -> Result<ImageInput<'_>, MagickError> {
+            Ok(ImageInput::Data(&self.mmap))
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub use mapped_image::MappedImage;
@@ -1,16 +1,32 @@
 use std::{
-    io::{self, Read},
+    fmt::{self, Debug, Formatter},
+    io::{self, Read, Write},
     path::{Path, PathBuf},
 };
 
 use magick_rust::MagickWand;
 
 /// The resource of an image. It can be an input resource or an output resource.
-#[derive(Debug)]
+///
+/// More kinds of resources may be added, so it is non-exhaustive.
+#[non_exhaustive]
 pub enum ImageResource {
     Path(String),
     Data(Vec<u8>),
     MagickWand(MagickWand),
+    /// An output-only resource. The encoded image is written to the sink and the sink is flushed, instead of the image being appended to a buffer held by the resource. The image is still encoded into a temporary buffer as a whole before it is written.
+    Writer(Box<dyn Write + Send>),
+}
+
+impl Debug for ImageResource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageResource::Path(p) => f.debug_tuple("Path").field(p).finish(),
+            ImageResource::Data(d) => f.debug_tuple("Data").field(d).finish(),
+            ImageResource::MagickWand(mw) => f.debug_tuple("MagickWand").field(mw).finish(),
+            ImageResource::Writer(_) => f.write_str("Writer(..)"),
+        }
+    }
 }
 
 impl ImageResource {
@@ -28,6 +44,25 @@ impl ImageResource {
         Ok(ImageResource::Data(buffer))
     }
 
+    /// Create an image resource from an async reader.
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(
+        mut reader: R,
+    ) -> Result<ImageResource, io::Error> {
+        use tokio::io::AsyncReadExt;
+
+        let mut buffer = Vec::new();
+
+        reader.read_to_end(&mut buffer).await?;
+
+        Ok(ImageResource::Data(buffer))
+    }
+
+    /// Create an output image resource from a writer.
+    pub fn from_writer<W: Write + Send + 'static>(writer: W) -> ImageResource {
+        ImageResource::Writer(Box::new(writer))
+    }
+
     /// Create an empty image resource with a specific capacity.
     pub fn with_capacity(capacity: usize) -> ImageResource {
         ImageResource::Data(Vec::with_capacity(capacity))
@@ -70,6 +105,15 @@ impl ImageResource {
             None
         }
     }
+
+    /// Convert this `ImageResource` instance into a writer (if it is possible).
+    pub fn into_writer(self) -> Option<Box<dyn Write + Send>> {
+        if let ImageResource::Writer(w) = self {
+            Some(w)
+        } else {
+            None
+        }
+    }
 }
 
 impl ImageResource {
@@ -44,16 +44,25 @@ let mut output = ImageResource::from_path(target_image_path);
 to_png(&mut output, &input, &config).unwrap();
 ```
 
-Supported output formats are `BMP`, `JPG`, `PNG`, `GIF`, `TIFF`, `WEBP`, `ICO`, `PGM` and `GrayRaw`.
+Supported output formats are `BMP`, `JPG`, `PNG`, `GIF`, `TIFF`, `WEBP`, `ICO`, `CUR`, `ICNS`, `PGM` and `GrayRaw`.
 */
 
 pub extern crate magick_rust;
 
+mod adjustments;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod batch;
+mod chroma_key;
 mod color_name;
+mod composition;
 mod crop;
+mod derivatives;
+mod effects;
 mod format_bmp;
 mod format_gif;
 mod format_gray_raw;
+mod format_icns;
 mod format_ico;
 mod format_jpeg;
 mod format_pgm;
@@ -63,16 +72,37 @@ mod format_webp;
 mod functions;
 mod identify;
 mod image_config;
+mod image_input;
 mod image_resource;
 mod interlace_type;
-
-use std::sync::Once;
-
+mod lut;
+mod palette;
+mod perceptual_hash;
+mod placeholder;
+mod read_policy;
+mod resize_mode;
+mod resource_limits;
+mod responsive;
+mod runtime;
+mod shape;
+mod transform;
+mod trim;
+mod watermark;
+
+pub use adjustments::*;
+#[cfg(feature = "tokio")]
+pub use asynchronous::*;
+pub use batch::*;
+pub use chroma_key::ChromaKey;
 pub use color_name::*;
+pub use composition::*;
 pub use crop::*;
+pub use derivatives::*;
+pub use effects::*;
 pub use format_bmp::*;
 pub use format_gif::*;
 pub use format_gray_raw::*;
+pub use format_icns::*;
 pub use format_ico::*;
 pub use format_jpeg::*;
 pub use format_pgm::*;
@@ -82,16 +112,25 @@ pub use format_webp::*;
 pub use functions::*;
 pub use identify::*;
 pub use image_config::*;
+pub use image_input::*;
 pub use image_resource::*;
 pub use interlace_type::InterlaceType;
-use magick_rust::magick_wand_genesis;
+pub use lut::*;
 pub use magick_rust::MagickError;
-
-static START: Once = Once::new();
-
-/// Call this function before using **MagickWand**.
+pub use palette::*;
+pub use perceptual_hash::*;
+pub use placeholder::*;
+pub use read_policy::*;
+pub use resize_mode::ResizeMode;
+pub use resource_limits::*;
+pub use responsive::*;
+pub use runtime::{DefaultConfigs, GlobalOptions, ImageConvert};
+pub use shape::*;
+pub use transform::*;
+pub use trim::*;
+pub use watermark::*;
+
+/// Call this function before using **MagickWand**. It initializes **MagickWand** implicitly if no `ImageConvert` runtime has done it, or again after a runtime has been dropped. A dropped runtime does not wait for the wands used directly after calling this function.
 pub static START_CALL_ONCE: fn() = || {
-    START.call_once(|| {
-        magick_wand_genesis();
-    });
+    runtime::ensure_genesis();
 };
@@ -0,0 +1,264 @@
+use std::{fs, path::PathBuf};
+
+use magick_rust::{MagickError, MagickWand};
+
+use crate::{
+    functions::{fetch_trusted_magic_wand, map_pixels},
+    image_config::ResizeConfig,
+    ImageResource,
+};
+
+/// The source of a 3D LUT.
+#[derive(Debug)]
+pub enum LutSource {
+    /// The path of a `.cube` file.
+    CubeFile(PathBuf),
+    /// The content of a `.cube` file.
+    Cube(String),
+    /// A Hald CLUT image. Its width and height must be the cube of its level, which is from 2 to 16. It is trusted, so the read policy of the `ImageConvert` runtime does not apply to it.
+    Hald(ImageResource),
+}
+
+#[derive(Debug)]
+/// A 3D LUT applied to grade the colors of an image after resizing. The source is parsed once when the instance is created.
+pub struct Lut {
+    table:        Table,
+    /// From 0 to 1, how much the graded colors are mixed with the original colors.
+    pub strength: f64,
+}
+
+impl Lut {
+    /// Create a `Lut` instance by parsing a LUT source, with default values.
+    /// ```rust,ignore
+    /// Lut {
+    ///     table,
+    ///     strength: 1f64,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new(source: LutSource) -> Result<Lut, MagickError> {
+        let table = match source {
+            LutSource::CubeFile(p) => match fs::read_to_string(p) {
+                Ok(cube) => Table::from_cube(&cube)?,
+                Err(_) => return Err("Cannot read the cube file.".into()),
+            },
+            LutSource::Cube(cube) => Table::from_cube(&cube)?,
+            LutSource::Hald(image) => Table::from_hald(&image)?,
+        };
+
+        Ok(Lut {
+            table,
+            strength: 1f64,
+        })
+    }
+
+    /// Apply the LUT to an image.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        let strength = self.strength.clamp(0f64, 1f64);
+
+        if strength == 0f64 {
+            return Ok(());
+        }
+
+        map_pixels(mw, |pixels, _, _| {
+            for pixel in pixels.chunks_exact_mut(3) {
+                let graded = self.table.lookup([pixel[0], pixel[1], pixel[2]]);
+
+                for (sample, graded) in pixel.iter_mut().zip(graded) {
+                    *sample += (graded - *sample) * strength;
+                }
+            }
+        })
+    }
+}
+
+/// The maximum size of a 3D LUT in a `.cube` file.
+const MAX_CUBE_SIZE: usize = 128;
+
+/// A 3D table whose red index changes the fastest. The values are from 0 to 1, and the input colors are mapped from the domain to the indices.
+#[derive(Debug)]
+struct Table {
+    size:       usize,
+    domain_min: [f64; 3],
+    domain_max: [f64; 3],
+    values:     Vec<[f64; 3]>,
+}
+
+impl Table {
+    fn from_cube(cube: &str) -> Result<Table, MagickError> {
+        let mut size = 0;
+        let mut domain_min = [0f64; 3];
+        let mut domain_max = [1f64; 3];
+        let mut values = Vec::new();
+
+        for line in cube.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+
+            let keyword = tokens.next().unwrap();
+
+            match keyword {
+                "TITLE" => (),
+                "LUT_1D_SIZE" => return Err("1D LUTs are not supported.".into()),
+                "LUT_3D_SIZE" => {
+                    size = match tokens.next().and_then(|s| s.parse::<usize>().ok()) {
+                        Some(size) if (2..=MAX_CUBE_SIZE).contains(&size) => size,
+                        _ => return Err("The size of the 3D LUT must be from 2 to 128.".into()),
+                    };
+                },
+                "DOMAIN_MIN" => domain_min = parse_triple(tokens)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(tokens)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    let mut range = [0f64; 2];
+
+                    for value in range.iter_mut() {
+                        *value = match tokens.next().and_then(|s| s.parse::<f64>().ok()) {
+                            Some(v) if v.is_finite() => v,
+                            _ => return Err("The input range of the 3D LUT is incorrect.".into()),
+                        };
+                    }
+
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                },
+                _ if keyword.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => {
+                    if size == 0 {
+                        return Err("The size of the 3D LUT is not defined.".into());
+                    }
+
+                    // the vector grows with the entries, so a forged size cannot allocate a huge table
+                    if values.len() == size * size * size {
+                        return Err(
+                            "The number of entries does not match the size of the 3D LUT.".into()
+                        );
+                    }
+
+                    values.push(parse_triple(line.split_whitespace())?);
+                },
+                // other keywords (such as `LUT_IN_VIDEO_RANGE` and `LUT_1D_INPUT_RANGE`) do not affect a 3D LUT
+                _ => (),
+            }
+        }
+
+        if size == 0 {
+            return Err("The size of the 3D LUT is not defined.".into());
+        }
+
+        if values.len() != size * size * size {
+            return Err("The number of entries does not match the size of the 3D LUT.".into());
+        }
+
+        for i in 0..3 {
+            if domain_max[i] <= domain_min[i] {
+                return Err("The domain of the 3D LUT is incorrect.".into());
+            }
+        }
+
+        for value in values.iter_mut() {
+            for v in value.iter_mut() {
+                *v = v.clamp(0f64, 1f64);
+            }
+        }
+
+        Ok(Table {
+            size,
+            domain_min,
+            domain_max,
+            values,
+        })
+    }
+
+    fn from_hald(image: &ImageResource) -> Result<Table, MagickError> {
+        let config = ResizeConfig {
+            width: 0, height: 0, crop: None
+        };
+
+        let (mw, _) = fetch_trusted_magic_wand(image, &config)?;
+
+        let width = mw.get_image_width();
+        let height = mw.get_image_height();
+
+        let level = match (2..=16).find(|level| level * level * level == width) {
+            Some(level) if width == height => level,
+            _ => return Err("The size of the Hald CLUT image is incorrect.".into()),
+        };
+
+        let pixels = match mw.export_image_pixels_double(0, 0, width, height, "RGB") {
+            Some(pixels) => pixels,
+            None => return Err("Cannot export the pixels of the Hald CLUT image.".into()),
+        };
+
+        let values = pixels
+            .chunks_exact(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]].map(|v| v.clamp(0f64, 1f64)))
+            .collect();
+
+        Ok(Table {
+            size: level * level,
+            domain_min: [0f64; 3],
+            domain_max: [1f64; 3],
+            values,
+        })
+    }
+
+    /// Look up a color with trilinear interpolation.
+    fn lookup(&self, rgb: [f64; 3]) -> [f64; 3] {
+        let max = (self.size - 1) as f64;
+
+        let mut indices = [0usize; 3];
+        let mut fractions = [0f64; 3];
+
+        for i in 0..3 {
+            let position = ((rgb[i] - self.domain_min[i])
+                / (self.domain_max[i] - self.domain_min[i]))
+                .clamp(0f64, 1f64)
+                * max;
+            let index = (position.floor() as usize).min(self.size - 2);
+
+            indices[i] = index;
+            fractions[i] = position - index as f64;
+        }
+
+        let mut result = [0f64; 3];
+
+        for corner in 0..8 {
+            let mut weight = 1f64;
+            let mut offset = 0;
+            let mut stride = 1;
+
+            for i in 0..3 {
+                let upper = corner >> i & 1;
+
+                weight *= if upper == 1 { fractions[i] } else { 1f64 - fractions[i] };
+                offset += (indices[i] + upper) * stride;
+                stride *= self.size;
+            }
+
+            if weight > 0f64 {
+                for (result, value) in result.iter_mut().zip(self.values[offset]) {
+                    *result += value * weight;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn parse_triple<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Result<[f64; 3], MagickError> {
+    let mut triple = [0f64; 3];
+
+    for value in triple.iter_mut() {
+        *value = match tokens.next().and_then(|s| s.parse::<f64>().ok()) {
+            Some(v) if v.is_finite() => v,
+            _ => return Err("The 3D LUT has an incorrect entry.".into()),
+        };
+    }
+
+    Ok(triple)
+}
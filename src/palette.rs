@@ -0,0 +1,237 @@
+use magick_rust::MagickError;
+
+use crate::{
+    compute_output_size, identify::read_magic_wand, runtime::begin_operation, AsImageInput,
+    ColorName,
+};
+
+/// Whether a color is vibrant or muted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorTone {
+    /// A saturated color which is neither too dark nor too light.
+    Vibrant,
+    /// A desaturated, very dark or very light color.
+    Muted,
+}
+
+/// A color extracted from an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteColor {
+    /// The RGBA value.
+    pub rgba:       [u8; 4],
+    /// From 0 to 1, the share of the pixels which have this color.
+    pub share:      f64,
+    /// The nearest color name, if the color is close enough to one.
+    pub color_name: Option<ColorName>,
+    /// Whether the color is vibrant or muted.
+    pub tone:       ColorTone,
+}
+
+impl PaletteColor {
+    fn new(rgba: [u8; 4], share: f64) -> PaletteColor {
+        let rgb = [rgba[0], rgba[1], rgba[2]];
+
+        PaletteColor {
+            rgba,
+            share,
+            color_name: nearest_color_name(rgb),
+            tone: classify_tone(rgb),
+        }
+    }
+}
+
+/// Extract the `n` dominant colors of an image, ordered by their shares. The colors are computed by quantizing a copy of the image downsampled to fit in 64x64 with the median cut algorithm, and transparent pixels are ignored.
+pub fn dominant_colors(
+    input: &(impl AsImageInput + ?Sized),
+    n: usize,
+) -> Result<Vec<PaletteColor>, MagickError> {
+    let pixels = downsampled_pixels(input)?;
+
+    // ignore (mostly) transparent pixels
+    let pixels: Vec<[u8; 4]> = pixels.into_iter().filter(|p| p[3] >= 128).collect();
+
+    if n == 0 || pixels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let total = pixels.len() as f64;
+
+    let mut buckets = vec![pixels];
+
+    while buckets.len() < n {
+        // split the bucket which has the widest channel range weighted by its size
+        let widest = buckets
+            .iter()
+            .enumerate()
+            .map(|(i, bucket)| {
+                let (channel, range) = widest_channel(bucket);
+
+                (i, channel, range as usize * bucket.len())
+            })
+            .filter(|(.., priority)| *priority > 0)
+            .max_by_key(|(.., priority)| *priority);
+
+        let (index, channel, _) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+
+        let mut bucket = buckets.swap_remove(index);
+
+        bucket.sort_unstable_by_key(|p| p[channel]);
+
+        // split at the boundary of two different values which is the nearest to the median, so a color is never divided
+        let median = bucket.len() / 2;
+
+        let split = (1..bucket.len())
+            .filter(|i| bucket[i - 1][channel] != bucket[*i][channel])
+            .min_by_key(|i| i.abs_diff(median))
+            .unwrap();
+
+        let upper = bucket.split_off(split);
+
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+
+    let mut colors: Vec<PaletteColor> = buckets
+        .iter()
+        .map(|bucket| PaletteColor::new(mean(bucket), bucket.len() as f64 / total))
+        .collect();
+
+    colors.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+    Ok(colors)
+}
+
+/// Compute the average color of an image. The RGB values are weighted by the alpha values.
+pub fn average_color(input: &(impl AsImageInput + ?Sized)) -> Result<PaletteColor, MagickError> {
+    let pixels = downsampled_pixels(input)?;
+
+    let mut sums = [0f64; 4];
+
+    for p in pixels.iter() {
+        let alpha = f64::from(p[3]);
+
+        for i in 0..3 {
+            sums[i] += f64::from(p[i]) * alpha;
+        }
+
+        sums[3] += alpha;
+    }
+
+    let mut rgba = [0u8; 4];
+
+    if sums[3] > 0f64 {
+        for i in 0..3 {
+            rgba[i] = (sums[i] / sums[3]).round() as u8;
+        }
+
+        rgba[3] = (sums[3] / pixels.len() as f64).round() as u8;
+    }
+
+    Ok(PaletteColor::new(rgba, 1f64))
+}
+
+/// Read an image and export the RGBA pixels of a copy of it which is sampled to fit in 64x64, keeping the original colors.
+fn downsampled_pixels(input: &(impl AsImageInput + ?Sized)) -> Result<Vec<[u8; 4]>, MagickError> {
+    let _operation = begin_operation();
+
+    let mw = read_magic_wand(input, None)?;
+
+    if let Some((width, height)) =
+        compute_output_size(true, mw.get_image_width() as u16, mw.get_image_height() as u16, 64, 64)
+    {
+        mw.sample_image(width as usize, height as usize)?;
+    }
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    if width == 0 || height == 0 {
+        return Err("The image is empty.".into());
+    }
+
+    match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+        Some(pixels) => Ok(pixels.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()),
+        None => Err("Cannot export the pixels of the image.".into()),
+    }
+}
+
+/// Find the RGB channel which has the widest range, and that range.
+fn widest_channel(pixels: &[[u8; 4]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), p| {
+                (min.min(p[channel]), max.max(p[channel]))
+            });
+
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn mean(pixels: &[[u8; 4]]) -> [u8; 4] {
+    let mut sums = [0u64; 4];
+
+    for p in pixels {
+        for (sum, sample) in sums.iter_mut().zip(p) {
+            *sum += u64::from(*sample);
+        }
+    }
+
+    sums.map(|sum| (sum as f64 / pixels.len() as f64).round() as u8)
+}
+
+/// Find the color name which is within a quarter of the largest distance between two colors.
+fn nearest_color_name(rgb: [u8; 3]) -> Option<ColorName> {
+    const NAMES: [ColorName; 8] = [
+        ColorName::White,
+        ColorName::Black,
+        ColorName::Red,
+        ColorName::Green,
+        ColorName::Blue,
+        ColorName::Yellow,
+        ColorName::Cyan,
+        ColorName::Magenta,
+    ];
+
+    let max_distance = 255f64 * 3f64.sqrt() / 4f64;
+
+    NAMES
+        .iter()
+        .map(|name| {
+            let distance = name
+                .as_rgb()
+                .iter()
+                .zip(rgb)
+                .map(|(a, b)| (f64::from(*a) - f64::from(b)).powi(2))
+                .sum::<f64>()
+                .sqrt();
+
+            (*name, distance)
+        })
+        .filter(|(_, distance)| *distance <= max_distance)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(name, _)| name)
+}
+
+/// Classify a color by its HSL saturation and lightness.
+fn classify_tone(rgb: [u8; 3]) -> ColorTone {
+    let [r, g, b] = rgb.map(|v| f64::from(v) / 255f64);
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+
+    let lightness = (max + min) / 2f64;
+
+    let saturation =
+        if max == min { 0f64 } else { (max - min) / (1f64 - (2f64 * lightness - 1f64).abs()) };
+
+    if saturation >= 0.4 && (0.2..=0.8).contains(&lightness) {
+        ColorTone::Vibrant
+    } else {
+        ColorTone::Muted
+    }
+}
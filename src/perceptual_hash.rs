@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+use magick_rust::{FilterType, MagickError, MagickWand};
+
+use crate::{
+    functions::handle_orientation, identify::read_magic_wand, runtime::begin_operation,
+    AsImageInput, ReadPolicy,
+};
+
+/// The algorithm of a perceptual hash. Every hash has 64 bits, so two hashes can be compared by their [Hamming distance](hamming_distance).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HashKind {
+    /// The average hash. Each bit tells whether a pixel of the 8x8 grayscale image is brighter than the mean.
+    Average,
+    /// The difference hash. Each bit tells whether a pixel of the 9x8 grayscale image is darker than its right neighbor.
+    Difference,
+    /// The perceptual hash. Each bit tells whether a low frequency of the DCT of the 32x32 grayscale image is larger than the median.
+    Perceptual,
+    /// The wavelet hash. Each bit tells whether a value of the LL band of the Haar wavelet transform of the 64x64 grayscale image is larger than the median.
+    Wavelet,
+}
+
+/// Compute the perceptual hash of an image, to find out images which look the same even after they are resized or recompressed.
+#[inline]
+pub fn perceptual_hash(
+    input: &(impl AsImageInput + ?Sized),
+    kind: HashKind,
+) -> Result<u64, MagickError> {
+    perceptual_hash_inner(input, kind, None)
+}
+
+/// Compute the perceptual hash of an image, enforcing a read policy.
+#[inline]
+pub fn perceptual_hash_with_policy(
+    input: &(impl AsImageInput + ?Sized),
+    kind: HashKind,
+    policy: &ReadPolicy,
+) -> Result<u64, MagickError> {
+    perceptual_hash_inner(input, kind, Some(policy))
+}
+
+/// Count the different bits of two hashes. The smaller the distance is, the more similar the images are.
+#[inline]
+pub const fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn perceptual_hash_inner(
+    input: &(impl AsImageInput + ?Sized),
+    kind: HashKind,
+    policy: Option<&ReadPolicy>,
+) -> Result<u64, MagickError> {
+    let _operation = begin_operation();
+
+    let mw = read_magic_wand(input, policy)?;
+
+    handle_orientation(&mw)?;
+
+    match kind {
+        HashKind::Average => {
+            let pixels = grayscale(&mw, 8, 8)?;
+
+            let mean = pixels.iter().sum::<f64>() / 64f64;
+
+            Ok(to_bits(pixels.iter().map(|v| *v > mean)))
+        },
+        HashKind::Difference => {
+            let pixels = grayscale(&mw, 9, 8)?;
+
+            Ok(to_bits(pixels.chunks_exact(9).flat_map(|row| row.windows(2).map(|w| w[0] < w[1]))))
+        },
+        HashKind::Perceptual => {
+            let pixels = grayscale(&mw, 32, 32)?;
+
+            // only the 8x8 lowest frequencies of the DCT-II are needed
+            let table: Vec<[f64; 32]> = (0..8)
+                .map(|u| {
+                    let mut row = [0f64; 32];
+
+                    for (x, v) in row.iter_mut().enumerate() {
+                        *v = (PI * (2 * x + 1) as f64 * u as f64 / 64f64).cos();
+                    }
+
+                    row
+                })
+                .collect();
+
+            let mut frequencies = Vec::with_capacity(64);
+
+            for cv in table.iter() {
+                for cu in table.iter() {
+                    let f = pixels
+                        .chunks_exact(32)
+                        .zip(cv)
+                        .map(|(row, cv)| row.iter().zip(cu).map(|(p, cu)| p * cu).sum::<f64>() * cv)
+                        .sum::<f64>();
+
+                    frequencies.push(f);
+                }
+            }
+
+            let median = median(&frequencies);
+
+            Ok(to_bits(frequencies.iter().map(|v| *v > median)))
+        },
+        HashKind::Wavelet => {
+            let mut pixels = grayscale(&mw, 64, 64)?;
+
+            // three levels of the Haar wavelet transform leave an 8x8 LL band
+            let mut size = 64;
+
+            while size > 8 {
+                let half = size / 2;
+
+                for y in 0..half {
+                    for x in 0..half {
+                        let i = 2 * y * 64 + 2 * x;
+
+                        pixels[y * 64 + x] =
+                            (pixels[i] + pixels[i + 1] + pixels[i + 64] + pixels[i + 65]) / 4f64;
+                    }
+                }
+
+                size = half;
+            }
+
+            let ll: Vec<f64> =
+                pixels.chunks_exact(64).take(8).flat_map(|row| &row[..8]).copied().collect();
+
+            let median = median(&ll);
+
+            Ok(to_bits(ll.iter().map(|v| *v > median)))
+        },
+    }
+}
+
+/// Get the luminance of an image resized to exactly `width`x`height`. The transparent parts are composited onto white.
+fn grayscale(mw: &MagickWand, width: usize, height: usize) -> Result<Vec<f64>, MagickError> {
+    let mw = mw.clone();
+
+    mw.resize_image(width, height, FilterType::Lanczos)?;
+
+    let pixels = match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+        Some(pixels) => pixels,
+        None => return Err("Cannot export the pixels of the image.".into()),
+    };
+
+    Ok(pixels
+        .chunks_exact(4)
+        .map(|p| {
+            let luma = 0.299 * f64::from(p[0]) + 0.587 * f64::from(p[1]) + 0.114 * f64::from(p[2]);
+            let alpha = f64::from(p[3]) / 255f64;
+
+            luma * alpha + 255f64 * (1f64 - alpha)
+        })
+        .collect())
+}
+
+#[inline]
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+
+    sorted.sort_by(f64::total_cmp);
+
+    let middle = sorted.len() / 2;
+
+    (sorted[middle - 1] + sorted[middle]) / 2f64
+}
+
+/// Pack 64 bits, the first one being the most significant.
+#[inline]
+fn to_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.take(64).fold(0u64, |hash, bit| (hash << 1) | bit as u64)
+}
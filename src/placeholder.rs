@@ -0,0 +1,661 @@
+use std::{
+    f64::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+use magick_rust::{AlphaChannelOption, FilterType, MagickError, MagickWand, PixelWand};
+
+use crate::{
+    compute_output_size, fetch_magic_wand, image_config::ResizeConfig, runtime::begin_operation,
+    AsImageInput, ImageConfig, ImageResource,
+};
+
+/// The kind of a placeholder hash.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlaceholderKind {
+    /// A [BlurHash](https://blurha.sh/) with the numbers of horizontal and vertical components, from 1 to 9.
+    BlurHash { components_x: u8, components_y: u8 },
+    /// A [ThumbHash](https://evanw.github.io/thumbhash/).
+    ThumbHash,
+}
+
+/// A placeholder hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placeholder {
+    BlurHash(String),
+    ThumbHash(Vec<u8>),
+}
+
+/// Receive the placeholder hash of an output image, computed from the resized image before it is encoded. It can be cloned and shared with an output config.
+///
+/// A sink keeps only one hash, which is replaced by every conversion using it. So each output needs its own sink (and its own config): if a config is shared by the outputs of `convert_many` or a `Batch`, which run concurrently, it is undefined which of their hashes the sink keeps. For an ICO or ICNS image, the sink keeps the hash of the last entry.
+#[derive(Debug, Clone)]
+pub struct PlaceholderSink {
+    kind:   PlaceholderKind,
+    result: Arc<Mutex<Option<Placeholder>>>,
+}
+
+impl PlaceholderSink {
+    /// Create a `PlaceholderSink` instance.
+    #[inline]
+    pub fn new(kind: PlaceholderKind) -> PlaceholderSink {
+        PlaceholderSink {
+            kind,
+            result: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Get the kind of the placeholder hash.
+    #[inline]
+    pub fn kind(&self) -> PlaceholderKind {
+        self.kind
+    }
+
+    /// Take the placeholder hash computed by the last conversion.
+    #[inline]
+    pub fn take(&self) -> Option<Placeholder> {
+        self.result.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+
+    /// Compute the placeholder hash of an image.
+    pub(crate) fn compute(&self, mw: &MagickWand) -> Result<(), MagickError> {
+        let placeholder = match self.kind {
+            PlaceholderKind::BlurHash {
+                components_x,
+                components_y,
+            } => Placeholder::BlurHash(blurhash_from_wand(mw, components_x, components_y)?),
+            PlaceholderKind::ThumbHash => Placeholder::ThumbHash(thumbhash_from_wand(mw)?),
+        };
+
+        *self.result.lock().unwrap_or_else(|err| err.into_inner()) = Some(placeholder);
+
+        Ok(())
+    }
+}
+
+/// Compute the [BlurHash](https://blurha.sh/) of an image, which is oriented. The numbers of components are from 1 to 9.
+#[inline]
+pub fn blurhash(
+    input: &(impl AsImageInput + ?Sized),
+    components_x: u8,
+    components_y: u8,
+) -> Result<String, MagickError> {
+    let config = ResizeConfig {
+        width: 0, height: 0, crop: None
+    };
+
+    blurhash_with_config(input, &config, components_x, components_y)
+}
+
+/// Compute the [BlurHash](https://blurha.sh/) of an image. The image is prepared (oriented, transformed, cropped and so on) with a config, and the numbers of components are from 1 to 9.
+pub fn blurhash_with_config(
+    input: &(impl AsImageInput + ?Sized),
+    config: &impl ImageConfig,
+    components_x: u8,
+    components_y: u8,
+) -> Result<String, MagickError> {
+    let _operation = begin_operation();
+
+    let (mw, _) = fetch_magic_wand(input, config)?;
+
+    blurhash_from_wand(&mw, components_x, components_y)
+}
+
+/// Compute the [ThumbHash](https://evanw.github.io/thumbhash/) of an image, which is oriented.
+#[inline]
+pub fn thumbhash(input: &(impl AsImageInput + ?Sized)) -> Result<Vec<u8>, MagickError> {
+    let config = ResizeConfig {
+        width: 0, height: 0, crop: None
+    };
+
+    thumbhash_with_config(input, &config)
+}
+
+/// Compute the [ThumbHash](https://evanw.github.io/thumbhash/) of an image. The image is prepared (oriented, transformed, cropped and so on) with a config.
+pub fn thumbhash_with_config(
+    input: &(impl AsImageInput + ?Sized),
+    config: &impl ImageConfig,
+) -> Result<Vec<u8>, MagickError> {
+    let _operation = begin_operation();
+
+    let (mw, _) = fetch_magic_wand(input, config)?;
+
+    thumbhash_from_wand(&mw)
+}
+
+/// Export the RGBA pixels of a copy of an image which is shrunk to fit in 100x100.
+fn thumbnail_rgba(mw: &MagickWand) -> Result<(usize, usize, Vec<u8>), MagickError> {
+    let mw = mw.clone();
+
+    if let Some((width, height)) = compute_output_size(
+        true,
+        mw.get_image_width() as u16,
+        mw.get_image_height() as u16,
+        100,
+        100,
+    ) {
+        mw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+    }
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    if width == 0 || height == 0 {
+        return Err("The image is empty.".into());
+    }
+
+    match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+        Some(pixels) => Ok((width, height, pixels)),
+        None => Err("Cannot export the pixels of the image.".into()),
+    }
+}
+
+/// Create an `ImageResource` instance from RGBA pixels.
+fn rgba_to_resource(
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<ImageResource, MagickError> {
+    let _operation = begin_operation();
+
+    let mut pw = PixelWand::new();
+    pw.set_color("none")?;
+
+    let mut mw = MagickWand::new();
+
+    mw.new_image(width, height, &pw)?;
+    mw.set_image_alpha_channel(AlphaChannelOption::Set)?;
+    mw.import_image_pixels(0, 0, width, height, pixels, "RGBA")?;
+
+    Ok(ImageResource::MagickWand(mw))
+}
+
+// BlurHash
+
+const BASE83: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+fn encode_base83(value: u32, length: u32, hash: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+
+        hash.push(BASE83[digit as usize] as char);
+    }
+}
+
+fn decode_base83(s: &str) -> Result<u32, MagickError> {
+    s.bytes().try_fold(0u32, |value, c| match BASE83.iter().position(|b| *b == c) {
+        Some(digit) => Ok(value * 83 + digit as u32),
+        None => Err("The BlurHash has an incorrect character.".into()),
+    })
+}
+
+#[inline]
+fn srgb_to_linear(v: u8) -> f64 {
+    let v = f64::from(v) / 255f64;
+
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+fn linear_to_srgb(v: f64) -> u32 {
+    let v = v.clamp(0f64, 1f64);
+
+    if v <= 0.0031308 {
+        (v * 12.92 * 255f64 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1f64 / 2.4) - 0.055) * 255f64 + 0.5) as u32
+    }
+}
+
+#[inline]
+fn sign_pow(v: f64, exp: f64) -> f64 {
+    v.abs().powf(exp).copysign(v)
+}
+
+fn blurhash_from_wand(
+    mw: &MagickWand,
+    components_x: u8,
+    components_y: u8,
+) -> Result<String, MagickError> {
+    if !(1..=9).contains(&components_x) || !(1..=9).contains(&components_y) {
+        return Err("The numbers of BlurHash components must be from 1 to 9.".into());
+    }
+
+    let (width, height, pixels) = thumbnail_rgba(mw)?;
+
+    let linear: Vec<[f64; 3]> = pixels
+        .chunks_exact(4)
+        .map(|p| [srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2])])
+        .collect();
+
+    let components_x = components_x as usize;
+    let components_y = components_y as usize;
+
+    let mut factors = Vec::with_capacity(components_x * components_y);
+
+    for j in 0..components_y {
+        for i in 0..components_x {
+            let normalization = if i == 0 && j == 0 { 1f64 } else { 2f64 };
+
+            let mut factor = [0f64; 3];
+
+            for y in 0..height {
+                let basis_y = (PI * j as f64 * y as f64 / height as f64).cos();
+
+                for x in 0..width {
+                    let basis =
+                        normalization * (PI * i as f64 * x as f64 / width as f64).cos() * basis_y;
+
+                    for (f, v) in factor.iter_mut().zip(linear[y * width + x]) {
+                        *f += basis * v;
+                    }
+                }
+            }
+
+            let scale = 1f64 / (width * height) as f64;
+
+            factors.push(factor.map(|f| f * scale));
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+
+    encode_base83(((components_x - 1) + (components_y - 1) * 9) as u32, 1, &mut hash);
+
+    let ac = &factors[1..];
+
+    let maximum = if ac.is_empty() {
+        encode_base83(0, 1, &mut hash);
+
+        1f64
+    } else {
+        let actual_maximum = ac.iter().flatten().fold(0f64, |m, v| m.max(v.abs()));
+
+        let quantised_maximum = (actual_maximum * 166f64 - 0.5).floor().clamp(0f64, 82f64) as u32;
+
+        encode_base83(quantised_maximum, 1, &mut hash);
+
+        (quantised_maximum + 1) as f64 / 166f64
+    };
+
+    let [r, g, b] = factors[0];
+
+    encode_base83(
+        (linear_to_srgb(r) << 16) + (linear_to_srgb(g) << 8) + linear_to_srgb(b),
+        4,
+        &mut hash,
+    );
+
+    for factor in ac {
+        let [r, g, b] = factor
+            .map(|v| (sign_pow(v / maximum, 0.5) * 9f64 + 9.5).floor().clamp(0f64, 18f64) as u32);
+
+        encode_base83(r * 19 * 19 + g * 19 + b, 2, &mut hash);
+    }
+
+    Ok(hash)
+}
+
+/// Render a [BlurHash](https://blurha.sh/) into an image.
+pub fn decode_blurhash(hash: &str, width: u16, height: u16) -> Result<ImageResource, MagickError> {
+    if hash.len() < 6 || !hash.is_ascii() {
+        return Err("The BlurHash is too short.".into());
+    }
+
+    if width == 0 || height == 0 {
+        return Err("The size of the image is incorrect.".into());
+    }
+
+    let size_flag = decode_base83(&hash[0..1])? as usize;
+
+    let components_x = size_flag % 9 + 1;
+    let components_y = size_flag / 9 + 1;
+
+    if hash.len() != 4 + 2 * components_x * components_y {
+        return Err("The length of the BlurHash is incorrect.".into());
+    }
+
+    let maximum = (decode_base83(&hash[1..2])? + 1) as f64 / 166f64;
+
+    let mut colors = Vec::with_capacity(components_x * components_y);
+
+    let dc = decode_base83(&hash[2..6])?;
+
+    if dc >> 24 != 0 {
+        return Err("The DC component of the BlurHash is incorrect.".into());
+    }
+
+    colors.push([(dc >> 16) as u8, (dc >> 8) as u8, dc as u8].map(srgb_to_linear));
+
+    for i in 1..components_x * components_y {
+        let value = decode_base83(&hash[4 + i * 2..6 + i * 2])?;
+
+        colors.push(
+            [value / (19 * 19), (value / 19) % 19, value % 19]
+                .map(|q| sign_pow((q as f64 - 9f64) / 9f64, 2f64) * maximum),
+        );
+    }
+
+    let width = width as usize;
+    let height = height as usize;
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0f64; 3];
+
+            for j in 0..components_y {
+                let basis_y = (PI * y as f64 * j as f64 / height as f64).cos();
+
+                for i in 0..components_x {
+                    let basis = (PI * x as f64 * i as f64 / width as f64).cos() * basis_y;
+
+                    for (p, c) in pixel.iter_mut().zip(colors[i + j * components_x]) {
+                        *p += c * basis;
+                    }
+                }
+            }
+
+            pixels.extend(pixel.map(|v| linear_to_srgb(v) as u8));
+            pixels.push(255);
+        }
+    }
+
+    rgba_to_resource(width, height, &pixels)
+}
+
+// ThumbHash
+
+/// Round like `Math.round` of JavaScript, which the reference implementation uses.
+#[inline]
+fn js_round(v: f64) -> u32 {
+    (v + 0.5).floor() as u32
+}
+
+/// The coefficients `(cx, cy)` of a channel with `nx` by `ny` terms, starting with the DC term. Only the low frequency triangle is kept.
+fn coefficients(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    (0..ny)
+        .flat_map(|cy| (0..).take_while(move |cx| cx * ny < nx * (ny - cy)).map(move |cx| (cx, cy)))
+        .collect()
+}
+
+/// Encode a channel using the DCT into the DC term, the normalized AC terms and the scale.
+fn encode_channel(
+    channel: &[f64],
+    width: usize,
+    height: usize,
+    nx: usize,
+    ny: usize,
+) -> (f64, Vec<f64>, f64) {
+    let mut dc = 0f64;
+    let mut ac = Vec::new();
+    let mut scale = 0f64;
+
+    let mut fx = vec![0f64; width];
+
+    for (cx, cy) in coefficients(nx, ny) {
+        let mut f = 0f64;
+
+        for (x, fx) in fx.iter_mut().enumerate() {
+            *fx = (PI / width as f64 * cx as f64 * (x as f64 + 0.5)).cos();
+        }
+
+        for (y, row) in channel.chunks_exact(width).enumerate() {
+            let fy = (PI / height as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+
+            f += row.iter().zip(&fx).map(|(v, fx)| v * fx * fy).sum::<f64>();
+        }
+
+        f /= (width * height) as f64;
+
+        if cx > 0 || cy > 0 {
+            ac.push(f);
+            scale = scale.max(f.abs());
+        } else {
+            dc = f;
+        }
+    }
+
+    if scale > 0f64 {
+        for v in ac.iter_mut() {
+            *v = 0.5 + 0.5 / scale * *v;
+        }
+    }
+
+    (dc, ac, scale)
+}
+
+fn thumbhash_from_wand(mw: &MagickWand) -> Result<Vec<u8>, MagickError> {
+    let (width, height, pixels) = thumbnail_rgba(mw)?;
+
+    let count = width * height;
+
+    // determine the average color
+    let mut average = [0f64; 4];
+
+    for p in pixels.chunks_exact(4) {
+        let alpha = f64::from(p[3]) / 255f64;
+
+        for (a, v) in average.iter_mut().zip(&p[..3]) {
+            *a += alpha / 255f64 * f64::from(*v);
+        }
+
+        average[3] += alpha;
+    }
+
+    if average[3] > 0f64 {
+        for i in 0..3 {
+            average[i] /= average[3];
+        }
+    }
+
+    let has_alpha = average[3] < count as f64;
+
+    // use fewer luminance bits if there is alpha
+    let l_limit = if has_alpha { 5f64 } else { 7f64 };
+    let max_side = width.max(height) as f64;
+    let lx = (js_round(l_limit * width as f64 / max_side) as usize).max(1);
+    let ly = (js_round(l_limit * height as f64 / max_side) as usize).max(1);
+
+    let mut l = Vec::with_capacity(count);
+    let mut p = Vec::with_capacity(count);
+    let mut q = Vec::with_capacity(count);
+    let mut a = Vec::with_capacity(count);
+
+    // convert the image from RGBA to LPQA (composite atop the average color)
+    for pixel in pixels.chunks_exact(4) {
+        let alpha = f64::from(pixel[3]) / 255f64;
+
+        let [r, g, b] =
+            [0, 1, 2].map(|i| average[i] * (1f64 - alpha) + alpha / 255f64 * f64::from(pixel[i]));
+
+        l.push((r + g + b) / 3f64);
+        p.push((r + g) / 2f64 - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, width, height, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, width, height, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, width, height, 3, 3);
+
+    let is_landscape = width > height;
+
+    let header24 = js_round(63f64 * l_dc)
+        | (js_round(31.5 + 31.5 * p_dc) << 6)
+        | (js_round(31.5 + 31.5 * q_dc) << 12)
+        | (js_round(31f64 * l_scale) << 18)
+        | ((has_alpha as u32) << 23);
+
+    let header16 = (if is_landscape { ly } else { lx }) as u32
+        | (js_round(63f64 * p_scale) << 3)
+        | (js_round(63f64 * q_scale) << 9)
+        | ((is_landscape as u32) << 15);
+
+    let mut hash = vec![
+        header24 as u8,
+        (header24 >> 8) as u8,
+        (header24 >> 16) as u8,
+        header16 as u8,
+        (header16 >> 8) as u8,
+    ];
+
+    let mut channels = vec![l_ac, p_ac, q_ac];
+
+    if has_alpha {
+        let (a_dc, a_ac, a_scale) = encode_channel(&a, width, height, 5, 5);
+
+        hash.push((js_round(15f64 * a_dc) | (js_round(15f64 * a_scale) << 4)) as u8);
+
+        channels.push(a_ac);
+    }
+
+    let ac_start = hash.len();
+
+    // write the varying factors
+    for (ac_index, f) in channels.iter().flatten().enumerate() {
+        let i = ac_start + (ac_index >> 1);
+
+        if i == hash.len() {
+            hash.push(0);
+        }
+
+        hash[i] |= (js_round(15f64 * f) << ((ac_index & 1) << 2)) as u8;
+    }
+
+    Ok(hash)
+}
+
+/// Render a [ThumbHash](https://evanw.github.io/thumbhash/) into an image which fits in 32x32.
+pub fn decode_thumbhash(hash: &[u8]) -> Result<ImageResource, MagickError> {
+    if hash.len() < 5 {
+        return Err("The ThumbHash is too short.".into());
+    }
+
+    let header24 = hash[0] as u32 | (hash[1] as u32) << 8 | (hash[2] as u32) << 16;
+    let header16 = hash[3] as u32 | (hash[4] as u32) << 8;
+
+    let l_dc = (header24 & 63) as f64 / 63f64;
+    let p_dc = ((header24 >> 6) & 63) as f64 / 31.5 - 1f64;
+    let q_dc = ((header24 >> 12) & 63) as f64 / 31.5 - 1f64;
+    let l_scale = ((header24 >> 18) & 31) as f64 / 31f64;
+    let has_alpha = header24 >> 23 != 0;
+    let p_scale = ((header16 >> 3) & 63) as f64 / 63f64;
+    let q_scale = ((header16 >> 9) & 63) as f64 / 63f64;
+    let is_landscape = header16 >> 15 != 0;
+
+    let l_limit = if has_alpha { 5 } else { 7 };
+    let (lx, ly) = if is_landscape {
+        (l_limit, (header16 & 7) as usize)
+    } else {
+        ((header16 & 7) as usize, l_limit)
+    };
+
+    if lx == 0 || ly == 0 {
+        return Err("The ThumbHash is incorrect.".into());
+    }
+
+    let ratio = lx as f64 / ly as f64;
+
+    let (lx, ly) = (lx.max(3), ly.max(3));
+
+    if has_alpha && hash.len() < 6 {
+        return Err("The ThumbHash is too short.".into());
+    }
+
+    let (a_dc, a_scale) = if has_alpha {
+        ((hash[5] & 15) as f64 / 15f64, (hash[5] >> 4) as f64 / 15f64)
+    } else {
+        (1f64, 0f64)
+    };
+
+    // read the varying factors (boost saturation by 1.25x to compensate for quantization)
+    let ac_start = if has_alpha { 6 } else { 5 };
+    let mut ac_index = 0;
+
+    let mut decode_channel = |nx: usize, ny: usize, scale: f64| -> Result<Vec<f64>, MagickError> {
+        let count = coefficients(nx, ny).len() - 1;
+
+        let start = ac_index;
+
+        ac_index += count;
+
+        (start..ac_index)
+            .map(|i| match hash.get(ac_start + (i >> 1)) {
+                Some(byte) => {
+                    let v = (byte >> ((i & 1) << 2)) & 15;
+
+                    Ok((v as f64 / 7.5 - 1f64) * scale)
+                },
+                None => Err("The ThumbHash is too short.".into()),
+            })
+            .collect()
+    };
+
+    let l_ac = decode_channel(lx, ly, l_scale)?;
+    let p_ac = decode_channel(3, 3, p_scale * 1.25)?;
+    let q_ac = decode_channel(3, 3, q_scale * 1.25)?;
+    let a_ac = if has_alpha { decode_channel(5, 5, a_scale)? } else { Vec::new() };
+
+    let width = js_round(if ratio > 1f64 { 32f64 } else { 32f64 * ratio }) as usize;
+    let height = js_round(if ratio > 1f64 { 32f64 / ratio } else { 32f64 }) as usize;
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+
+    let nx = lx.max(if has_alpha { 5 } else { 3 });
+    let ny = ly.max(if has_alpha { 5 } else { 3 });
+
+    // skip the DC terms
+    let l_coefficients = &coefficients(lx, ly)[1..];
+    let pq_coefficients = &coefficients(3, 3)[1..];
+    let a_coefficients = &coefficients(5, 5)[1..];
+
+    let mut fx = vec![0f64; nx];
+    let mut fy = vec![0f64; ny];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (mut l, mut p, mut q, mut a) = (l_dc, p_dc, q_dc, a_dc);
+
+            // precompute the coefficients
+            for (cx, fx) in fx.iter_mut().enumerate() {
+                *fx = (PI / width as f64 * (x as f64 + 0.5) * cx as f64).cos();
+            }
+
+            for (cy, fy) in fy.iter_mut().enumerate() {
+                *fy = (PI / height as f64 * (y as f64 + 0.5) * cy as f64).cos();
+            }
+
+            // decode L, P, Q and A
+            for ((cx, cy), ac) in l_coefficients.iter().zip(&l_ac) {
+                l += ac * fx[*cx] * fy[*cy] * 2f64;
+            }
+
+            for ((cx, cy), (p_ac, q_ac)) in pq_coefficients.iter().zip(p_ac.iter().zip(&q_ac)) {
+                let f = fx[*cx] * fy[*cy] * 2f64;
+
+                p += p_ac * f;
+                q += q_ac * f;
+            }
+
+            for ((cx, cy), ac) in a_coefficients.iter().zip(&a_ac) {
+                a += ac * fx[*cx] * fy[*cy] * 2f64;
+            }
+
+            // convert to RGB
+            let b = l - 2f64 / 3f64 * p;
+            let r = (3f64 * l - b + q) / 2f64;
+            let g = r - q;
+
+            pixels.extend([r, g, b, a].map(|v| (255f64 * v.clamp(0f64, 1f64)).round() as u8));
+        }
+    }
+
+    rgba_to_resource(width, height, &pixels)
+}
@@ -0,0 +1,436 @@
+use std::{fs::File, io::Read, path::Path};
+
+use magick_rust::MagickError;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// The error message used when an input path uses indirection which is forbidden.
+const INDIRECTION_FORBIDDEN: &str =
+    "The input path uses indirection which is forbidden by the read policy.";
+
+/// The number of leading bytes used to sniff the format of an input image.
+const SNIFF_LENGTH: usize = 4096;
+
+/// Formats which are decoded by external delegates (Ghostscript, etc.), interpreters or which can fetch other resources.
+const DELEGATE_FORMATS: [&str; 28] = [
+    "PS", "PS2", "PS3", "EPS", "EPSF", "EPSI", "EPI", "EPT", "PDF", "PDFA", "AI", "XPS", "PCL",
+    "SVG", "MSVG", "MVG", "MSL", "URL", "HTTP", "HTTPS", "FTP", "TEXT", "TXT", "LABEL", "CAPTION",
+    "PANGO", "SHOW", "HTML",
+];
+
+/// Other ImageMagick coders, which are recognized in `format:` prefixes and extensions.
+const CODERS: [&str; 129] = [
+    "AAI",
+    "ART",
+    "AVIF",
+    "AVS",
+    "BGR",
+    "BGRA",
+    "BMP",
+    "BMP2",
+    "BMP3",
+    "CANVAS",
+    "CIN",
+    "CLIPBOARD",
+    "CMYK",
+    "CMYKA",
+    "CUR",
+    "CUT",
+    "DCM",
+    "DCX",
+    "DDS",
+    "DIB",
+    "DJVU",
+    "DNG",
+    "DPX",
+    "EPHEMERAL",
+    "EXR",
+    "FARBFELD",
+    "FAX",
+    "FD",
+    "FITS",
+    "FL32",
+    "G3",
+    "GIF",
+    "GIF87",
+    "GRADIENT",
+    "GRANITE",
+    "GRAY",
+    "GRAYA",
+    "HALD",
+    "HDR",
+    "HEIC",
+    "HEIF",
+    "HISTOGRAM",
+    "HRZ",
+    "ICB",
+    "ICO",
+    "ICON",
+    "INFO",
+    "INLINE",
+    "J2C",
+    "J2K",
+    "JNG",
+    "JP2",
+    "JPE",
+    "JPEG",
+    "JPG",
+    "JXL",
+    "LOGO",
+    "MAP",
+    "MASK",
+    "MIFF",
+    "MONO",
+    "MPC",
+    "MPR",
+    "MTV",
+    "NETSCAPE",
+    "ORA",
+    "OTB",
+    "PAL",
+    "PALM",
+    "PAM",
+    "PATTERN",
+    "PBM",
+    "PCD",
+    "PCX",
+    "PDB",
+    "PFM",
+    "PGM",
+    "PGX",
+    "PICT",
+    "PIX",
+    "PLASMA",
+    "PNG",
+    "PNG00",
+    "PNG24",
+    "PNG32",
+    "PNG48",
+    "PNG64",
+    "PNG8",
+    "PNM",
+    "PPM",
+    "PSB",
+    "PSD",
+    "PTIF",
+    "QOI",
+    "RADIAL-GRADIENT",
+    "RAS",
+    "RAW",
+    "RGB",
+    "RGBA",
+    "RGBO",
+    "RGF",
+    "ROSE",
+    "SCREENSHOT",
+    "SFW",
+    "SGI",
+    "SUN",
+    "SVGZ",
+    "TGA",
+    "TIF",
+    "TIFF",
+    "TIFF64",
+    "TILE",
+    "TIM",
+    "UYVY",
+    "VICAR",
+    "VID",
+    "VIFF",
+    "WBMP",
+    "WEBP",
+    "WIZARD",
+    "WPG",
+    "X",
+    "XBM",
+    "XC",
+    "XCF",
+    "XPM",
+    "XWD",
+    "YCBCR",
+    "YUV",
+];
+
+static RE_FORMAT_PREFIX: Lazy<Regex> = Lazy::new(|| Regex::new("^([A-Za-z0-9_-]{2,}):").unwrap());
+static RE_FRAME_SUFFIX: Lazy<Regex> = Lazy::new(|| Regex::new("\\[[^\\]]*\\]$").unwrap());
+
+/// The policy used to restrict how an input image can be read by ImageMagick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadPolicy {
+    /// The allowed input formats, using ImageMagick format names such as `"JPEG"` or `"PNG"`. An empty list means all formats are allowed.
+    pub allowed_formats:         Vec<String>,
+    /// Forbid formats which are decoded by external delegates or interpreters, or which can reference other resources, such as PostScript, PDF, SVG, MVG, MSL and `url:`. Since ImageMagick could pick such a coder by itself, an input whose format cannot be recognized from its magic number (or the extension of its path) is rejected too.
+    pub forbid_delegates:        bool,
+    /// Forbid path-based indirection on `ImageResource::Path`, such as `@file`, `|command`, `format:` prefixes and `[...]` suffixes.
+    pub forbid_path_indirection: bool,
+}
+
+impl ReadPolicy {
+    /// Create a `ReadPolicy` instance with default values.
+    /// ```rust,ignore
+    /// ReadPolicy {
+    ///     allowed_formats: Vec::new(),
+    ///     forbid_delegates: true,
+    ///     forbid_path_indirection: true,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> ReadPolicy {
+        ReadPolicy {
+            allowed_formats:         Vec::new(),
+            forbid_delegates:        true,
+            forbid_path_indirection: true,
+        }
+    }
+
+    /// Create a `ReadPolicy` instance which only allows the common web raster formats (`JPEG`, `PNG`, `GIF`, `WEBP`).
+    #[inline]
+    pub fn web() -> ReadPolicy {
+        ReadPolicy {
+            allowed_formats: ["JPEG", "PNG", "GIF", "WEBP"].iter().map(|s| s.to_string()).collect(),
+            ..ReadPolicy::new()
+        }
+    }
+
+    /// Check whether a format (an ImageMagick format name) is allowed by this policy.
+    pub fn is_format_allowed<S: AsRef<str>>(&self, format: S) -> bool {
+        let format = canonical_format(format.as_ref());
+
+        if self.forbid_delegates && DELEGATE_FORMATS.contains(&format.as_str()) {
+            return false;
+        }
+
+        self.allowed_formats.is_empty()
+            || self.allowed_formats.iter().any(|f| canonical_format(f) == format)
+    }
+
+    /// Check an input path and detect its format from its leading bytes (or its extension) before ImageMagick touches it. The returned string is the path which should be passed to ImageMagick.
+    pub(crate) fn check_path(
+        &self,
+        path: &str,
+    ) -> Result<(String, Option<&'static str>), MagickError> {
+        if path.starts_with('@') || path.starts_with('|') {
+            if self.forbid_path_indirection {
+                return Err(INDIRECTION_FORBIDDEN.into());
+            }
+
+            // the images listed in a file or written by a command cannot be sniffed
+            let format = self.check_format(None)?;
+
+            return Ok((path.to_string(), format));
+        }
+
+        let prefix = coder_prefix(path);
+        let suffixed = RE_FRAME_SUFFIX.is_match(path);
+
+        if self.forbid_path_indirection && (prefix.is_some() || suffixed) {
+            return Err(INDIRECTION_FORBIDDEN.into());
+        }
+
+        if let Some(format) = prefix {
+            // the coder is already pinned by the prefix
+            self.check_format(Some(format))?;
+
+            return Ok((path.to_string(), Some(format)));
+        }
+
+        let file = RE_FRAME_SUFFIX.replace(path, "");
+
+        let mut head = Vec::with_capacity(SNIFF_LENGTH);
+
+        File::open(file.as_ref())
+            .and_then(|f| f.take(SNIFF_LENGTH as u64).read_to_end(&mut head))
+            .map_err(|_| "Cannot read the input file.")?;
+
+        let format = self.check_format(sniff_format(&head).or_else(|| extension_format(&file)))?;
+
+        // pin the coder so that ImageMagick cannot pick another one by itself
+        match format {
+            Some(format) => Ok((format!("{format}:{path}"), Some(format))),
+            None => Ok((path.to_string(), None)),
+        }
+    }
+
+    /// Sniff the leading bytes of an input image and check the detected format. The detected format should be pinned as the coder of the image.
+    #[inline]
+    pub(crate) fn check_head(&self, head: &[u8]) -> Result<Option<&'static str>, MagickError> {
+        self.check_format(sniff_format(head))
+    }
+
+    /// Check a detected format. An unknown format is only allowed if this policy allows every format, since ImageMagick would pick the coder by itself.
+    fn check_format(
+        &self,
+        format: Option<&'static str>,
+    ) -> Result<Option<&'static str>, MagickError> {
+        match format {
+            Some(format) => {
+                if !self.is_format_allowed(format) {
+                    return Err("The input format is not allowed by the read policy.".into());
+                }
+
+                Ok(Some(format))
+            },
+            None => {
+                if self.forbid_delegates || !self.allowed_formats.is_empty() {
+                    return Err("The input format cannot be recognized.".into());
+                }
+
+                Ok(None)
+            },
+        }
+    }
+
+    /// Check the format reported by ImageMagick against the sniffed format and the allowed formats.
+    pub(crate) fn check_identified(
+        &self,
+        sniffed: Option<&str>,
+        identified: &str,
+    ) -> Result<(), MagickError> {
+        let mut identified = canonical_format(identified);
+
+        if let Some(sniffed) = sniffed {
+            let sniffed = canonical_format(sniffed);
+
+            // the internal SVG renderer of ImageMagick reports SVG images as MVG
+            if sniffed == "SVG" && identified == "MVG" {
+                identified = sniffed;
+            } else if sniffed != identified {
+                return Err("The input format does not match its content.".into());
+            }
+        }
+
+        if !self.is_format_allowed(identified) {
+            return Err("The input format is not allowed by the read policy.".into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ReadPolicy {
+    #[inline]
+    fn default() -> Self {
+        ReadPolicy::new()
+    }
+}
+
+/// Get the known ImageMagick coder of a name.
+fn known_coder(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_uppercase();
+
+    DELEGATE_FORMATS.iter().chain(CODERS.iter()).find(|coder| **coder == name).copied()
+}
+
+/// Get the coder of the `format:` prefix of a path. A prefix which is not a known coder (such as in `2024-01-01T10:00.jpg`) is a part of the file name.
+fn coder_prefix(path: &str) -> Option<&'static str> {
+    RE_FORMAT_PREFIX.captures(path).and_then(|c| known_coder(&c[1]))
+}
+
+/// Get the coder of the extension of a path.
+fn extension_format(path: &str) -> Option<&'static str> {
+    Path::new(path).extension().and_then(|e| e.to_str()).and_then(known_coder)
+}
+
+/// Normalize the aliases of an ImageMagick format name.
+fn canonical_format(format: &str) -> String {
+    let format = format.to_ascii_uppercase();
+
+    match format.as_str() {
+        "JPG" | "JPE" | "JFIF" => String::from("JPEG"),
+        "TIF" | "TIFF64" | "PTIF" => String::from("TIFF"),
+        "ICON" => String::from("ICO"),
+        "MSVG" | "RSVG" | "SVGZ" => String::from("SVG"),
+        "HEIF" => String::from("HEIC"),
+        "PNG8" | "PNG24" | "PNG32" | "PNG48" | "PNG64" | "PNG00" => String::from("PNG"),
+        _ => format,
+    }
+}
+
+/// Detect the format of an image from its leading bytes (magic numbers). The returned value is an ImageMagick format name.
+pub fn sniff_format(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("JPEG");
+    }
+
+    if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some("PNG");
+    }
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some("GIF");
+    }
+
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("WEBP");
+    }
+
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some("TIFF");
+    }
+
+    if data.starts_with(b"BM") {
+        return Some("BMP");
+    }
+
+    if data.starts_with(&[0, 0, 1, 0]) {
+        return Some("ICO");
+    }
+
+    if data.starts_with(&[0, 0, 2, 0]) {
+        return Some("CUR");
+    }
+
+    if data.starts_with(b"8BPS") {
+        return Some("PSD");
+    }
+
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return match &data[8..12] {
+            b"avif" | b"avis" => Some("AVIF"),
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => Some("HEIC"),
+            _ => None,
+        };
+    }
+
+    if data.starts_with(b"%PDF") {
+        return Some("PDF");
+    }
+
+    if data.starts_with(b"%!PS") {
+        return Some("PS");
+    }
+
+    if data.starts_with(&[0xC5, 0xD0, 0xD3, 0xC6]) {
+        return Some("EPT");
+    }
+
+    if data.len() >= 3 && data[0] == b'P' && data[2].is_ascii_whitespace() {
+        match data[1] {
+            b'1' | b'4' => return Some("PBM"),
+            b'2' | b'5' => return Some("PGM"),
+            b'3' | b'6' => return Some("PPM"),
+            b'7' => return Some("PAM"),
+            _ => (),
+        }
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_start_matches('\u{FEFF}').trim_start();
+
+    if text.starts_with('<') {
+        let lowercase = text.to_ascii_lowercase();
+
+        if lowercase.contains("<svg") {
+            return Some("SVG");
+        }
+
+        // other XML documents can only be decoded as MSL scripts
+        return Some("MSL");
+    }
+
+    if text.starts_with("push graphic-context") || text.starts_with("viewbox") {
+        return Some("MVG");
+    }
+
+    None
+}
@@ -0,0 +1,10 @@
+/// How an image is resized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Resample the image with the Lanczos filter, keeping its aspect ratio.
+    Resample,
+    /// If both the width and the height are set, fill exactly that size by removing or inserting seams of the least important pixels (seam carving), so the aspect ratio can change without cropping the subject or stretching it. Otherwise, the image is resampled.
+    ///
+    /// It uses the liquid rescale of ImageMagick, which needs the **liblqr** delegate. If `fallback` is `true` and the delegate is not available, the image is resized to cover the size and the center is cropped; otherwise, an error is returned.
+    ContentAware { fallback: bool },
+}
@@ -0,0 +1,227 @@
+use std::time::{Duration, Instant};
+
+use magick_rust::{MagickError, MagickWand, ResourceType};
+use once_cell::sync::Lazy;
+
+/// The error message used when an input image exceeds the resource limits.
+pub const LIMIT_EXCEEDED: &str = "The input image exceeds the resource limits.";
+
+/// The messages (in lowercase) of the ImageMagick errors caused by exceeding its resource limits.
+const RESOURCE_LIMIT_MESSAGES: [&str; 6] = [
+    "width or height exceeds limit",
+    "list length exceeds limit",
+    "cache resources exhausted",
+    "pixel cache allocation failed",
+    "memory allocation failed",
+    "time limit exceeded",
+];
+
+/// The image artifact which stores the deadline of a conversion, in milliseconds since `EPOCH`.
+const DEADLINE_ARTIFACT: &str = "image-convert:deadline";
+
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Check whether an error is caused by exceeding the resource limits.
+#[inline]
+pub fn is_limit_exceeded(error: &MagickError) -> bool {
+    error.to_string() == LIMIT_EXCEEDED
+}
+
+/// The limits used to defend against decompression bombs. The input image is pinged and checked before it is fully read.
+///
+/// The ImageMagick resource limits are shared by the whole process, so they are not set for each conversion. The width, height, frame, memory, disk and map limits of the `resource_limits` of an `ImageConvert` runtime are set as ImageMagick resource limits once, when the runtime is initialized, and the previous limits are restored when it is dropped. ImageMagick resource limits can only be set on Linux and macOS. Elsewhere, only the ping checks and the timeout apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The maximum number of pixels (width × height) of the input image. `0` means unlimited.
+    pub max_pixels: u64,
+    /// The maximum width of the input image. `0` means unlimited.
+    pub max_width:  u32,
+    /// The maximum height of the input image. `0` means unlimited.
+    pub max_height: u32,
+    /// The maximum number of frames of the input image. `0` means unlimited.
+    pub max_frames: usize,
+    /// The maximum amount of memory (in bytes) ImageMagick can allocate for the pixel cache. `0` means unlimited.
+    pub memory:     u64,
+    /// The maximum amount of disk space (in bytes) ImageMagick can use for the pixel cache. `0` means unlimited.
+    pub disk:       u64,
+    /// The maximum amount of memory-mapped space (in bytes) ImageMagick can use for the pixel cache. `0` means unlimited.
+    pub map:        u64,
+    /// The maximum wall-clock time a conversion is allowed to spend, measured from the start of the call. It is checked between the stages of the conversion (reading, resizing and post-processing), so a single stage can overrun it.
+    pub timeout:    Option<Duration>,
+}
+
+impl ResourceLimits {
+    /// Create a `ResourceLimits` instance with default values.
+    /// ```rust,ignore
+    /// ResourceLimits {
+    ///     max_pixels: 100_000_000u64,
+    ///     max_width: 0u32,
+    ///     max_height: 0u32,
+    ///     max_frames: 256usize,
+    ///     memory: 1024 * 1024 * 1024u64,
+    ///     disk: 4 * 1024 * 1024 * 1024u64,
+    ///     map: 2 * 1024 * 1024 * 1024u64,
+    ///     timeout: None,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> ResourceLimits {
+        ResourceLimits {
+            max_pixels: 100_000_000u64,
+            max_width:  0u32,
+            max_height: 0u32,
+            max_frames: 256usize,
+            memory:     1024 * 1024 * 1024u64,
+            disk:       4 * 1024 * 1024 * 1024u64,
+            map:        2 * 1024 * 1024 * 1024u64,
+            timeout:    None,
+        }
+    }
+
+    /// Get the limits which are also ImageMagick resource limits. Unlimited ones are left out.
+    pub(crate) fn magick_limits(&self) -> Vec<(ResourceType, u64)> {
+        let limits = [
+            (ResourceType::Width, u64::from(self.max_width)),
+            (ResourceType::Height, u64::from(self.max_height)),
+            (ResourceType::ListLength, self.max_frames as u64),
+            (ResourceType::Memory, self.memory),
+            (ResourceType::Disk, self.disk),
+            (ResourceType::Map, self.map),
+        ];
+
+        limits.into_iter().filter(|(_, limit)| *limit > 0).collect()
+    }
+
+    /// Compute the deadline of a conversion which starts now.
+    #[inline]
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        Lazy::force(&EPOCH);
+
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Check a pinged image against the limits.
+    pub(crate) fn check(&self, mw: &MagickWand) -> Result<(), MagickError> {
+        let width = mw.get_image_width() as u64;
+        let height = mw.get_image_height() as u64;
+
+        if (self.max_width > 0 && width > u64::from(self.max_width))
+            || (self.max_height > 0 && height > u64::from(self.max_height))
+            || (self.max_pixels > 0 && width * height > self.max_pixels)
+            || (self.max_frames > 0 && mw.get_number_images() > self.max_frames)
+        {
+            return Err(LIMIT_EXCEEDED.into());
+        }
+
+        Ok(())
+    }
+
+    /// Turn the resource limit errors reported by ImageMagick into the `LIMIT_EXCEEDED` error.
+    pub(crate) fn map_error(&self, error: MagickError) -> MagickError {
+        let message = error.to_string().to_lowercase();
+
+        if RESOURCE_LIMIT_MESSAGES.iter().any(|m| message.contains(m)) {
+            LIMIT_EXCEEDED.into()
+        } else {
+            error
+        }
+    }
+}
+
+impl Default for ResourceLimits {
+    #[inline]
+    fn default() -> Self {
+        ResourceLimits::new()
+    }
+}
+
+/// Store the deadline of a conversion in an image.
+pub(crate) fn set_deadline(mw: &mut MagickWand, deadline: Instant) -> Result<(), MagickError> {
+    let millis = deadline.saturating_duration_since(*EPOCH).as_millis();
+
+    mw.set_image_artifact(DEADLINE_ARTIFACT, millis.to_string().as_str())?;
+
+    check_deadline(mw)
+}
+
+/// Check whether the deadline stored in an image (if any) has passed.
+pub(crate) fn check_deadline(mw: &MagickWand) -> Result<(), MagickError> {
+    if let Ok(millis) = mw.get_image_artifact(DEADLINE_ARTIFACT) {
+        if let Ok(millis) = millis.parse::<u128>() {
+            if EPOCH.elapsed().as_millis() >= millis {
+                return Err(LIMIT_EXCEEDED.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the deadline stored in an image (if any) for the last time and remove it, so that it is not left in an output image.
+pub(crate) fn finish_deadline(mw: &mut MagickWand) -> Result<(), MagickError> {
+    if mw.get_image_artifact(DEADLINE_ARTIFACT).is_ok() {
+        check_deadline(mw)?;
+
+        mw.delete_image_artifact(DEADLINE_ARTIFACT)?;
+    }
+
+    Ok(())
+}
+
+/// Get the current limit of an ImageMagick resource.
+#[inline]
+pub(crate) fn get_resource_limit(resource: ResourceType) -> u64 {
+    unsafe { magick_rust::bindings::MagickGetResourceLimit(resource) }
+}
+
+/// Set an ImageMagick resource limit, which is shared by the whole process. Resource limits can only be set on Linux and macOS, so this does nothing elsewhere.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[inline]
+pub(crate) fn set_resource_limit(resource: ResourceType, limit: u64) -> Result<(), MagickError> {
+    MagickWand::set_resource_limit(resource, limit)
+}
+
+/// Set an ImageMagick resource limit, which is shared by the whole process. Resource limits can only be set on Linux and macOS, so this does nothing elsewhere.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[inline]
+pub(crate) fn set_resource_limit(_resource: ResourceType, _limit: u64) -> Result<(), MagickError> {
+    Ok(())
+}
+
+/// Set ImageMagick resource limits, which are shared by the whole process, and restore the previous limits when it is dropped (or restored explicitly).
+#[derive(Debug, Default)]
+pub(crate) struct ResourceLimitGuard {
+    previous: Vec<(ResourceType, u64)>,
+}
+
+impl ResourceLimitGuard {
+    pub(crate) fn set(limits: &[(ResourceType, u64)]) -> Result<ResourceLimitGuard, MagickError> {
+        let mut guard = ResourceLimitGuard {
+            previous: Vec::with_capacity(limits.len())
+        };
+
+        for (resource, limit) in limits.iter().copied() {
+            let previous = get_resource_limit(resource);
+
+            set_resource_limit(resource, limit)?;
+
+            guard.previous.push((resource, previous));
+        }
+
+        Ok(guard)
+    }
+
+    /// Restore the previous limits.
+    pub(crate) fn restore(&mut self) {
+        for (resource, limit) in self.previous.drain(..).rev() {
+            let _ = set_resource_limit(resource, limit);
+        }
+    }
+}
+
+impl Drop for ResourceLimitGuard {
+    #[inline]
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
@@ -0,0 +1,344 @@
+use magick_rust::MagickError;
+
+use crate::{
+    functions::Preparation, identify_ping, AsImageInput, Crop, DerivativeSet, ImageResource,
+    JPGConfig, OutputConfig, PNGConfig, ReadPolicy, ResourceLimits, WEBPConfig,
+};
+
+/// The output formats of a responsive image set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResponsiveFormat {
+    JPG,
+    PNG,
+    WEBP,
+}
+
+impl ResponsiveFormat {
+    /// Get the MIME type of this format.
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::JPG => "image/jpeg",
+            Self::PNG => "image/png",
+            Self::WEBP => "image/webp",
+        }
+    }
+
+    /// Get the file extension name (without the dot) of this format.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::JPG => "jpg",
+            Self::PNG => "png",
+            Self::WEBP => "webp",
+        }
+    }
+}
+
+/// The config of a responsive image set.
+#[derive(Debug)]
+pub struct ResponsiveConfig {
+    /// Remove the metadata stored in the input image.
+    pub strip_metadata:      bool,
+    /// The widths (in CSS pixels) of the output images.
+    pub widths:              Vec<u16>,
+    /// The pixel densities of the output images. Each width is multiplied by each density.
+    pub densities:           Vec<u8>,
+    /// The output formats. The last one is used as the fallback of the `<img>` element.
+    pub formats:             Vec<ResponsiveFormat>,
+    /// Crop the image.
+    pub crop:                Option<Crop>,
+    /// Skip the widths which are larger than the width of the input image. If every width is skipped, the width of the input image is used.
+    pub shrink_only:         bool,
+    /// The higher the sharper. A negative value means auto adjustment.
+    pub sharpen:             f64,
+    /// Apply orientation from image metadata if available, whether the image is read from a file, from data or from a `MagickWand` instance.
+    pub respect_orientation: bool,
+    /// From 1 to 100, the higher the better. Used by JPEG and WebP.
+    pub quality:             u8,
+    /// Restrict how the input image can be read.
+    pub read_policy:         Option<ReadPolicy>,
+    /// Limit the resources used to read the input image.
+    pub resource_limits:     Option<ResourceLimits>,
+}
+
+impl ResponsiveConfig {
+    /// Create a `ResponsiveConfig` instance with default values.
+    /// ```rust,ignore
+    /// ResponsiveConfig {
+    ///     strip_metadata: true,
+    ///     widths: vec![320, 640, 960, 1280, 1920],
+    ///     densities: vec![1, 2],
+    ///     formats: vec![ResponsiveFormat::WEBP, ResponsiveFormat::JPG],
+    ///     crop: None,
+    ///     shrink_only: true,
+    ///     sharpen: -1f64,
+    ///     respect_orientation: false,
+    ///     quality: 85u8,
+    ///     read_policy: None,
+    ///     resource_limits: None,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new() -> ResponsiveConfig {
+        ResponsiveConfig {
+            strip_metadata:      true,
+            widths:              vec![320, 640, 960, 1280, 1920],
+            densities:           vec![1, 2],
+            formats:             vec![ResponsiveFormat::WEBP, ResponsiveFormat::JPG],
+            crop:                None,
+            shrink_only:         true,
+            sharpen:             -1f64,
+            respect_orientation: false,
+            quality:             85u8,
+            read_policy:         None,
+            resource_limits:     None,
+        }
+    }
+}
+
+impl Default for ResponsiveConfig {
+    #[inline]
+    fn default() -> Self {
+        ResponsiveConfig::new()
+    }
+}
+
+/// An image of a responsive image set.
+#[derive(Debug)]
+pub struct ResponsiveImage {
+    pub width:  u16,
+    pub height: u16,
+    pub format: ResponsiveFormat,
+    pub data:   Vec<u8>,
+}
+
+impl ResponsiveImage {
+    /// Get the size of the encoded data in bytes.
+    #[inline]
+    pub fn byte_size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// A responsive image set.
+#[derive(Debug)]
+pub struct ResponsiveSet {
+    pub images: Vec<ResponsiveImage>,
+}
+
+impl ResponsiveSet {
+    /// Render an HTML `<picture>` element for this set. `url` maps each image to its URL.
+    pub fn to_html<F: Fn(&ResponsiveImage) -> String>(
+        &self,
+        alt: &str,
+        sizes: &str,
+        url: F,
+    ) -> String {
+        let mut formats: Vec<ResponsiveFormat> = Vec::new();
+
+        for image in self.images.iter() {
+            if !formats.contains(&image.format) {
+                formats.push(image.format);
+            }
+        }
+
+        let fallback = match formats.pop() {
+            Some(format) => format,
+            None => return String::new(),
+        };
+
+        let srcset = |format: ResponsiveFormat| {
+            self.images
+                .iter()
+                .filter(|image| image.format == format)
+                .map(|image| format!("{} {}w", escape_html(&url(image)), image.width))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut html = String::from("<picture>\n");
+
+        for format in formats {
+            html.push_str(&format!(
+                "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+                format.mime_type(),
+                srcset(format),
+                escape_html(sizes)
+            ));
+        }
+
+        // the largest fallback image is used as the `src` attribute
+        if let Some(image) = self
+            .images
+            .iter()
+            .filter(|image| image.format == fallback)
+            .max_by_key(|image| image.width)
+        {
+            html.push_str(&format!(
+                "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" \
+                 alt=\"{}\">\n",
+                escape_html(&url(image)),
+                srcset(fallback),
+                escape_html(sizes),
+                image.width,
+                image.height,
+                escape_html(alt)
+            ));
+        }
+
+        html.push_str("</picture>");
+
+        html
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Generate a responsive image set. The input image is decoded only once.
+pub fn responsive_set(
+    input: &(impl AsImageInput + ?Sized),
+    config: &ResponsiveConfig,
+) -> Result<ResponsiveSet, MagickError> {
+    let set =
+        DerivativeSet::new(input, config.read_policy.as_ref(), config.resource_limits.as_ref())?;
+
+    let (input_width, _) = set.prepared_size(Preparation {
+        respect_orientation: config.respect_orientation,
+        transform:           None,
+        chroma_key:          None,
+        trim:                None,
+        crop:                config.crop,
+    })?;
+
+    let mut widths: Vec<u16> = Vec::new();
+
+    for width in config.widths.iter().copied() {
+        for density in config.densities.iter().copied() {
+            let width =
+                (u32::from(width) * u32::from(density.max(1))).min(u32::from(u16::MAX)) as u16;
+
+            if config.shrink_only && width > input_width {
+                continue;
+            }
+
+            if !widths.contains(&width) {
+                widths.push(width);
+            }
+        }
+    }
+
+    // the input image is smaller than every width, so it is used as it is
+    if widths.is_empty() && config.shrink_only && !config.widths.is_empty() {
+        widths.push(input_width);
+    }
+
+    widths.sort_unstable();
+
+    let mut images = Vec::with_capacity(widths.len() * config.formats.len());
+    let mut jpg_configs = Vec::new();
+    let mut png_configs = Vec::new();
+    let mut webp_configs = Vec::new();
+
+    for width in widths.iter().copied() {
+        for format in config.formats.iter().copied() {
+            match format {
+                ResponsiveFormat::JPG => {
+                    let mut c = JPGConfig::new();
+
+                    c.strip_metadata = config.strip_metadata;
+                    c.width = width;
+                    c.crop = config.crop;
+                    c.shrink_only = config.shrink_only;
+                    c.sharpen = config.sharpen;
+                    c.respect_orientation = config.respect_orientation;
+                    c.quality = config.quality;
+
+                    jpg_configs.push(c);
+                },
+                ResponsiveFormat::PNG => {
+                    let mut c = PNGConfig::new();
+
+                    c.strip_metadata = config.strip_metadata;
+                    c.width = width;
+                    c.crop = config.crop;
+                    c.shrink_only = config.shrink_only;
+                    c.sharpen = config.sharpen;
+                    c.respect_orientation = config.respect_orientation;
+
+                    png_configs.push(c);
+                },
+                ResponsiveFormat::WEBP => {
+                    let mut c = WEBPConfig::new();
+
+                    c.strip_metadata = config.strip_metadata;
+                    c.width = width;
+                    c.crop = config.crop;
+                    c.shrink_only = config.shrink_only;
+                    c.sharpen = config.sharpen;
+                    c.respect_orientation = config.respect_orientation;
+                    c.quality = config.quality;
+
+                    webp_configs.push(c);
+                },
+            }
+
+            // the size is filled from the encoded image
+            images.push(ResponsiveImage {
+                width: 0,
+                height: 0,
+                format,
+                data: Vec::new(),
+            });
+        }
+    }
+
+    let mut jpg_configs = jpg_configs.iter();
+    let mut png_configs = png_configs.iter();
+    let mut webp_configs = webp_configs.iter();
+
+    let mut outputs: Vec<(OutputConfig, ImageResource)> = images
+        .iter()
+        .map(|image| {
+            let config = match image.format {
+                ResponsiveFormat::JPG => OutputConfig::JPG(jpg_configs.next().unwrap()),
+                ResponsiveFormat::PNG => OutputConfig::PNG(png_configs.next().unwrap()),
+                ResponsiveFormat::WEBP => OutputConfig::WEBP(webp_configs.next().unwrap()),
+            };
+
+            (config, ImageResource::with_capacity(0))
+        })
+        .collect();
+
+    {
+        let mut targets: Vec<(OutputConfig, &mut ImageResource)> =
+            outputs.iter_mut().map(|(config, output)| (*config, output)).collect();
+
+        set.convert(&mut targets)?;
+    }
+
+    for (image, (_, output)) in images.iter_mut().zip(outputs) {
+        image.data = output.into_vec().unwrap_or_default();
+
+        let id = identify_ping(&image.data)?;
+
+        image.width = id.resolution.width as u16;
+        image.height = id.resolution.height as u16;
+    }
+
+    Ok(ResponsiveSet {
+        images,
+    })
+}
@@ -0,0 +1,462 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+};
+
+use magick_rust::{
+    magick_wand_genesis, magick_wand_terminus, MagickError, MagickWand, ResourceType,
+};
+
+use crate::{
+    average_color, blurhash, dominant_colors, identify_ping, identify_read, perceptual_hash,
+    resource_limits::ResourceLimitGuard, thumbhash, to_bmp, to_cur, to_gif, to_gray_raw, to_icns,
+    to_ico, to_ico_from_sources, to_jpg, to_pgm, to_png, to_tiff, to_webp, AsImageInput, BMPConfig,
+    GIFConfig, GrayRawConfig, HashKind, ICNSConfig, ICOConfig, ImageIdentify, ImageResource,
+    JPGConfig, PGMConfig, PNGConfig, PaletteColor, ReadPolicy, ResourceLimits, TIFFConfig,
+    WEBPConfig,
+};
+
+#[derive(Debug, Default)]
+struct RuntimeState {
+    options: Option<Arc<GlobalOptions>>,
+}
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<RuntimeState> = Mutex::new(RuntimeState {
+    options: None
+});
+
+/// The number of operations in progress.
+static OPERATIONS: Mutex<usize> = Mutex::new(0);
+static OPERATIONS_DONE: Condvar = Condvar::new();
+
+#[inline]
+fn lock_state() -> MutexGuard<'static, RuntimeState> {
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[inline]
+fn lock_operations() -> MutexGuard<'static, usize> {
+    OPERATIONS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// An operation of this crate in progress. A dropped `ImageConvert` runtime waits for every operation to finish before terminating **MagickWand**.
+#[derive(Debug)]
+pub(crate) struct Operation {
+    _private: (),
+}
+
+/// Start an operation, and initialize **MagickWand** if needed.
+pub(crate) fn begin_operation() -> Operation {
+    *lock_operations() += 1;
+
+    ensure_genesis();
+
+    Operation {
+        _private: ()
+    }
+}
+
+impl Drop for Operation {
+    #[inline]
+    fn drop(&mut self) {
+        let mut operations = lock_operations();
+
+        *operations -= 1;
+
+        if *operations == 0 {
+            OPERATIONS_DONE.notify_all();
+        }
+    }
+}
+
+/// Initialize **MagickWand** if it has not been initialized. This is the implicit initialization used when no `ImageConvert` runtime is created.
+pub(crate) fn ensure_genesis() {
+    if INITIALIZED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let _state = lock_state();
+
+    if !INITIALIZED.load(Ordering::Acquire) {
+        magick_wand_genesis();
+
+        INITIALIZED.store(true, Ordering::Release);
+    }
+}
+
+/// Get the options of the current `ImageConvert` runtime (if any).
+pub(crate) fn global_options() -> Option<Arc<GlobalOptions>> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return None;
+    }
+
+    lock_state().options.clone()
+}
+
+/// The global options of ImageMagick, used by `ImageConvert::init`.
+#[derive(Debug, Clone)]
+pub struct GlobalOptions {
+    /// The maximum number of threads ImageMagick can use for an operation. `0` means the default of ImageMagick.
+    pub threads:         usize,
+    /// The directory where ImageMagick puts its temporary files. `None` means the default of ImageMagick.
+    pub temporary_path:  Option<PathBuf>,
+    /// The resource limits used when a config (or an identification function) does not set its own. Their width, height, frame, memory, disk and map limits are also set as ImageMagick resource limits while the runtime is alive.
+    pub resource_limits: Option<ResourceLimits>,
+    /// The read policy used when a config (or an identification function) does not set its own.
+    pub read_policy:     Option<ReadPolicy>,
+}
+
+impl GlobalOptions {
+    /// Create a `GlobalOptions` instance with default values.
+    /// ```rust,ignore
+    /// GlobalOptions {
+    ///     threads: 0usize,
+    ///     temporary_path: None,
+    ///     resource_limits: None,
+    ///     read_policy: None,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> GlobalOptions {
+        GlobalOptions {
+            threads:         0usize,
+            temporary_path:  None,
+            resource_limits: None,
+            read_policy:     None,
+        }
+    }
+}
+
+impl Default for GlobalOptions {
+    #[inline]
+    fn default() -> Self {
+        GlobalOptions::new()
+    }
+}
+
+/// The default configs of an `ImageConvert` runtime, used by its conversion methods.
+#[derive(Debug)]
+pub struct DefaultConfigs {
+    pub bmp:      BMPConfig,
+    pub gif:      GIFConfig,
+    pub gray_raw: GrayRawConfig,
+    pub icns:     ICNSConfig,
+    pub ico:      ICOConfig,
+    pub jpg:      JPGConfig,
+    pub pgm:      PGMConfig,
+    pub png:      PNGConfig,
+    pub tiff:     TIFFConfig,
+    pub webp:     WEBPConfig,
+}
+
+impl DefaultConfigs {
+    /// Create a `DefaultConfigs` instance whose configs all have default values.
+    #[inline]
+    pub fn new() -> DefaultConfigs {
+        DefaultConfigs {
+            bmp:      BMPConfig::new(),
+            gif:      GIFConfig::new(),
+            gray_raw: GrayRawConfig::new(),
+            icns:     ICNSConfig::new(),
+            ico:      ICOConfig::new(),
+            jpg:      JPGConfig::new(),
+            pgm:      PGMConfig::new(),
+            png:      PNGConfig::new(),
+            tiff:     TIFFConfig::new(),
+            webp:     WEBPConfig::new(),
+        }
+    }
+}
+
+impl Default for DefaultConfigs {
+    #[inline]
+    fn default() -> Self {
+        DefaultConfigs::new()
+    }
+}
+
+/// An explicit ImageMagick runtime. While it is alive, every function of this crate uses its options, and its conversion methods use its default configs. Its methods borrow the runtime, so it cannot be dropped while they are running.
+///
+/// Only one runtime can exist at a time. Without a runtime, **MagickWand** is initialized implicitly on the first use.
+///
+/// When a runtime is dropped, it restores the resource limits and the temporary path it set, waits for the operations of this crate in progress (in other threads) to finish, and terminates **MagickWand** by `magick_wand_terminus`. **MagickWand** is initialized implicitly again on the next use. Wands created while the runtime is alive and kept outside of this crate (such as the ones held by an `ImageResource::MagickWand` output, a `DerivativeSet` or a rendered `Composition`) must be dropped before the runtime.
+#[derive(Debug)]
+pub struct ImageConvert {
+    options:        Arc<GlobalOptions>,
+    configs:        DefaultConfigs,
+    limits:         ResourceLimitGuard,
+    temporary_path: Option<Option<OsString>>,
+}
+
+macro_rules! conversion_method {
+    ($(#[$attr:meta])* $name:ident, $config:ident) => {
+        $(#[$attr])*
+        #[inline]
+        pub fn $name(
+            &self,
+            output: &mut ImageResource,
+            input: &(impl AsImageInput + ?Sized),
+        ) -> Result<(), MagickError> {
+            $name(output, input, &self.configs.$config)
+        }
+    };
+}
+
+impl ImageConvert {
+    conversion_method!(
+        /// Convert an image to a BMP image with the default BMP config.
+        to_bmp, bmp
+    );
+
+    conversion_method!(
+        /// Convert an image to a CUR image with the default ICO config.
+        to_cur, ico
+    );
+
+    conversion_method!(
+        /// Convert an image to a GIF image with the default GIF config.
+        to_gif, gif
+    );
+
+    conversion_method!(
+        /// Convert an image to a raw grayscale image with the default GrayRaw config.
+        to_gray_raw, gray_raw
+    );
+
+    conversion_method!(
+        /// Convert an image to an ICNS image with the default ICNS config.
+        to_icns, icns
+    );
+
+    conversion_method!(
+        /// Convert an image to an ICO image with the default ICO config.
+        to_ico, ico
+    );
+
+    conversion_method!(
+        /// Convert an image to a JPEG image with the default JPG config.
+        to_jpg, jpg
+    );
+
+    conversion_method!(
+        /// Convert an image to a PGM image with the default PGM config.
+        to_pgm, pgm
+    );
+
+    conversion_method!(
+        /// Convert an image to a PNG image with the default PNG config.
+        to_png, png
+    );
+
+    conversion_method!(
+        /// Convert an image to a TIFF image with the default TIFF config.
+        to_tiff, tiff
+    );
+
+    conversion_method!(
+        /// Convert an image to a WebP image with the default WEBP config.
+        to_webp, webp
+    );
+
+    /// Convert images to an ICO image with the default ICO config, using a different source image for each size.
+    #[inline]
+    pub fn to_ico_from_sources(
+        &self,
+        output: &mut ImageResource,
+        inputs: &[(&ImageResource, (u16, u16))],
+    ) -> Result<(), MagickError> {
+        to_ico_from_sources(output, inputs, &self.configs.ico)
+    }
+
+    /// Ping and identify an image.
+    #[inline]
+    pub fn identify_ping(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+    ) -> Result<ImageIdentify, MagickError> {
+        identify_ping(input)
+    }
+
+    /// Read and identify an image. It can read an image as `MagickWand` instances, which must be dropped before this runtime.
+    #[inline]
+    pub fn identify_read(
+        &self,
+        output: &mut Option<MagickWand>,
+        input: &(impl AsImageInput + ?Sized),
+    ) -> Result<ImageIdentify, MagickError> {
+        identify_read(output, input)
+    }
+
+    /// Compute the perceptual hash of an image.
+    #[inline]
+    pub fn perceptual_hash(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+        kind: HashKind,
+    ) -> Result<u64, MagickError> {
+        perceptual_hash(input, kind)
+    }
+
+    /// Extract the `n` dominant colors of an image.
+    #[inline]
+    pub fn dominant_colors(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+        n: usize,
+    ) -> Result<Vec<PaletteColor>, MagickError> {
+        dominant_colors(input, n)
+    }
+
+    /// Compute the average color of an image.
+    #[inline]
+    pub fn average_color(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+    ) -> Result<PaletteColor, MagickError> {
+        average_color(input)
+    }
+
+    /// Compute the [BlurHash](https://blurha.sh/) of an image. The numbers of components are from 1 to 9.
+    #[inline]
+    pub fn blurhash(
+        &self,
+        input: &(impl AsImageInput + ?Sized),
+        components_x: u8,
+        components_y: u8,
+    ) -> Result<String, MagickError> {
+        blurhash(input, components_x, components_y)
+    }
+
+    /// Compute the [ThumbHash](https://evanw.github.io/thumbhash/) of an image.
+    #[inline]
+    pub fn thumbhash(&self, input: &(impl AsImageInput + ?Sized)) -> Result<Vec<u8>, MagickError> {
+        thumbhash(input)
+    }
+
+    /// Initialize **MagickWand** with global options. The default configs have default values.
+    ///
+    /// The temporary path is passed to ImageMagick through the `MAGICK_TEMPORARY_PATH` environment variable, which is only read when **MagickWand** is initialized, so setting it fails if **MagickWand** has already been initialized. Since the environment is shared by the whole process, a runtime with a temporary path should be initialized before other threads are started.
+    #[inline]
+    pub fn init(options: GlobalOptions) -> Result<ImageConvert, MagickError> {
+        ImageConvert::init_with_configs(options, DefaultConfigs::new())
+    }
+
+    /// Initialize **MagickWand** with global options and default configs.
+    pub fn init_with_configs(
+        options: GlobalOptions,
+        configs: DefaultConfigs,
+    ) -> Result<ImageConvert, MagickError> {
+        let mut state = lock_state();
+
+        if state.options.is_some() {
+            return Err("An ImageConvert runtime has already been initialized.".into());
+        }
+
+        let initialized = INITIALIZED.load(Ordering::Acquire);
+
+        if let Some(temporary_path) = options.temporary_path.as_ref() {
+            if initialized {
+                return Err(
+                    "The temporary path cannot be set after MagickWand is initialized.".into()
+                );
+            }
+
+            if !temporary_path.is_dir() {
+                return Err("The temporary path is not a directory.".into());
+            }
+        }
+
+        let previous_temporary_path = options.temporary_path.as_ref().map(|temporary_path| {
+            let previous = env::var_os("MAGICK_TEMPORARY_PATH");
+
+            env::set_var("MAGICK_TEMPORARY_PATH", temporary_path);
+
+            previous
+        });
+
+        if !initialized {
+            magick_wand_genesis();
+
+            INITIALIZED.store(true, Ordering::Release);
+        }
+
+        let mut limits =
+            options.resource_limits.as_ref().map(|l| l.magick_limits()).unwrap_or_default();
+
+        if options.threads > 0 {
+            limits.push((ResourceType::Thread, options.threads as u64));
+        }
+
+        let limits = ResourceLimitGuard::set(&limits)?;
+
+        let options = Arc::new(options);
+
+        state.options = Some(options.clone());
+
+        Ok(ImageConvert {
+            options,
+            configs,
+            limits,
+            temporary_path: previous_temporary_path,
+        })
+    }
+
+    /// Get the global options of this runtime.
+    #[inline]
+    pub fn options(&self) -> &GlobalOptions {
+        &self.options
+    }
+
+    /// Get the default configs of this runtime.
+    #[inline]
+    pub fn configs(&self) -> &DefaultConfigs {
+        &self.configs
+    }
+
+    /// Get the default configs of this runtime mutably.
+    #[inline]
+    pub fn configs_mut(&mut self) -> &mut DefaultConfigs {
+        &mut self.configs
+    }
+}
+
+impl Drop for ImageConvert {
+    #[inline]
+    fn drop(&mut self) {
+        {
+            let mut state = lock_state();
+
+            self.limits.restore();
+
+            match self.temporary_path.take() {
+                Some(Some(previous)) => env::set_var("MAGICK_TEMPORARY_PATH", previous),
+                Some(None) => env::remove_var("MAGICK_TEMPORARY_PATH"),
+                None => (),
+            }
+
+            state.options = None;
+        }
+
+        let operations = lock_operations();
+
+        // new operations wait for the termination
+        let _operations = OPERATIONS_DONE
+            .wait_while(operations, |operations| *operations > 0)
+            .unwrap_or_else(|err| err.into_inner());
+
+        let state = lock_state();
+
+        // another runtime may have been initialized in the meantime
+        if state.options.is_none() && INITIALIZED.load(Ordering::Acquire) {
+            magick_wand_terminus();
+
+            INITIALIZED.store(false, Ordering::Release);
+        }
+    }
+}
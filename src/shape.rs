@@ -0,0 +1,265 @@
+use magick_rust::{
+    AlphaChannelOption, CompositeOperator, FilterType, MagickError, MagickWand, PixelWand,
+};
+
+use crate::functions::parse_color;
+
+/// The mask of a shape. The area outside the mask becomes transparent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShapeMask {
+    /// The largest circle in the center of the image.
+    Circle,
+    /// A rectangle with rounded corners. The value is the radius of the corners (in pixels).
+    RoundedRectangle(f64),
+    /// A superellipse which fills the image, between a rectangle and an ellipse.
+    Squircle,
+}
+
+impl ShapeMask {
+    /// Compute the signed distance (in pixels) from a point to the edge of the mask. The point is relative to the center of the image, and a negative distance means it is inside the mask.
+    fn distance(self, x: f64, y: f64, half_width: f64, half_height: f64) -> f64 {
+        match self {
+            Self::Circle => (x * x + y * y).sqrt() - half_width.min(half_height),
+            Self::RoundedRectangle(radius) => {
+                let radius = radius.clamp(0f64, half_width.min(half_height));
+
+                let qx = x.abs() - (half_width - radius);
+                let qy = y.abs() - (half_height - radius);
+
+                let outside = (qx.max(0f64).powi(2) + qy.max(0f64).powi(2)).sqrt();
+
+                outside + qx.max(qy).min(0f64) - radius
+            },
+            Self::Squircle => {
+                let f = ((x / half_width).powi(4) + (y / half_height).powi(4)).powf(0.25);
+
+                (f - 1f64) * half_width.min(half_height)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A drop shadow drawn under a shape. The image is shrunk within its size to leave room for the shadow, so that the shadow is not clipped.
+pub struct Shadow {
+    /// The horizontal and vertical offsets (in pixels).
+    pub offset:  (i16, i16),
+    /// The sigma of the gaussian blur (in pixels).
+    pub blur:    f64,
+    /// The color of the shadow, such as `"#000000"`.
+    pub color:   String,
+    /// From 0 to 1, the opacity of the shadow.
+    pub opacity: f64,
+}
+
+impl Shadow {
+    /// Create a `Shadow` instance with default values.
+    /// ```rust,ignore
+    /// Shadow {
+    ///     offset: (0i16, 4i16),
+    ///     blur: 8f64,
+    ///     color: String::from("#000000"),
+    ///     opacity: 0.5f64,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new() -> Shadow {
+        Shadow {
+            offset:  (0i16, 4i16),
+            blur:    8f64,
+            color:   String::from("#000000"),
+            opacity: 0.5f64,
+        }
+    }
+}
+
+impl Default for Shadow {
+    #[inline]
+    fn default() -> Self {
+        Shadow::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Shape an image after it is resized, with an optional border and an optional drop shadow.
+pub struct Shape {
+    /// The mask.
+    pub mask:         ShapeMask,
+    /// The width of the border drawn inside the edge of the mask (in pixels). `0` means no border.
+    pub border_width: f64,
+    /// The color of the border, such as `"#FFFFFF"`.
+    pub border_color: String,
+    /// The drop shadow.
+    pub shadow:       Option<Shadow>,
+}
+
+impl Shape {
+    /// Create a `Shape` instance with default values.
+    /// ```rust,ignore
+    /// Shape {
+    ///     mask,
+    ///     border_width: 0f64,
+    ///     border_color: String::from("#FFFFFF"),
+    ///     shadow: None,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new(mask: ShapeMask) -> Shape {
+        Shape {
+            mask,
+            border_width: 0f64,
+            border_color: String::from("#FFFFFF"),
+            shadow: None,
+        }
+    }
+
+    /// Apply the shape to an image. The size of the image is kept, so if there is a drop shadow, the image is shrunk to leave room for it.
+    pub(crate) fn apply(&self, mw: &mut MagickWand) -> Result<(), MagickError> {
+        let margins = match self.shadow.as_ref() {
+            Some(shadow) => {
+                let margins = shadow_margins(mw, shadow);
+
+                if margins != (0, 0) {
+                    mw.resize_image(
+                        mw.get_image_width() - margins.0 * 2,
+                        mw.get_image_height() - margins.1 * 2,
+                        FilterType::Lanczos,
+                    )?;
+                }
+
+                margins
+            },
+            None => (0, 0),
+        };
+
+        let width = mw.get_image_width();
+        let height = mw.get_image_height();
+
+        mw.set_image_alpha_channel(AlphaChannelOption::Set)?;
+
+        let mut pixels = match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+            Some(pixels) => pixels,
+            None => return Err("Cannot export the pixels of the image.".into()),
+        };
+
+        let border_width = self.border_width.max(0f64);
+        let border_color = parse_color(&self.border_color)?.map(f64::from);
+
+        let half_width = width as f64 / 2f64;
+        let half_height = height as f64 / 2f64;
+
+        for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+            let x = (i % width) as f64 + 0.5 - half_width;
+            let y = (i / width) as f64 + 0.5 - half_height;
+
+            let distance = self.mask.distance(x, y, half_width, half_height);
+
+            // anti-alias the edges within one pixel
+            let coverage = (0.5 - distance).clamp(0f64, 1f64);
+
+            if border_width > 0f64 {
+                let inner = (0.5 - distance - border_width).clamp(0f64, 1f64);
+
+                for (sample, border) in pixel.iter_mut().zip(border_color) {
+                    *sample = (border + (f64::from(*sample) - border) * inner).round() as u8;
+                }
+
+                // the border is opaque
+                pixel[3] = (255f64 + (f64::from(pixel[3]) - 255f64) * inner).round() as u8;
+            }
+
+            pixel[3] = (f64::from(pixel[3]) * coverage).round() as u8;
+        }
+
+        mw.import_image_pixels(0, 0, width, height, &pixels, "RGBA")?;
+
+        if let Some(shadow) = self.shadow.as_ref() {
+            let alpha: Vec<u8> = pixels.iter().skip(3).step_by(4).copied().collect();
+
+            draw_shadow(mw, shadow, &alpha, margins)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compute the horizontal and vertical margins reserved for a drop shadow. The image is scaled into the remaining area with its aspect ratio (almost) kept.
+fn shadow_margins(mw: &MagickWand, shadow: &Shadow) -> (usize, usize) {
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let margin = (shadow.blur.max(0f64) * 3f64).ceil() as usize
+        + shadow.offset.0.unsigned_abs().max(shadow.offset.1.unsigned_abs()) as usize;
+
+    if margin == 0 {
+        return (0, 0);
+    }
+
+    let ratio = (width.saturating_sub(margin * 2) as f64 / width as f64)
+        .min(height.saturating_sub(margin * 2) as f64 / height as f64);
+
+    // the margins are rounded up so that the inner area is at least one pixel and the canvas size is kept exactly
+    let fit = |length: usize| {
+        let inner = (length as f64 * ratio).round() as usize;
+
+        ((length - inner).div_ceil(2)).min((length - 1) / 2)
+    };
+
+    (fit(width), fit(height))
+}
+
+/// Put an image back on a canvas extended by the margins, and draw its drop shadow under it. The properties of the image are kept.
+fn draw_shadow(
+    mw: &MagickWand,
+    shadow: &Shadow,
+    alpha: &[u8],
+    margins: (usize, usize),
+) -> Result<(), MagickError> {
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let blur = shadow.blur.max(0f64);
+    let (offset_x, offset_y) = (shadow.offset.0 as isize, shadow.offset.1 as isize);
+
+    let color = parse_color(&shadow.color)?;
+    let opacity = shadow.opacity.clamp(0f64, 1f64);
+
+    let mut shadow_pixels = Vec::with_capacity(alpha.len() * 4);
+
+    for a in alpha {
+        shadow_pixels.extend(color);
+        shadow_pixels.push((f64::from(*a) * opacity).round() as u8);
+    }
+
+    let mut pw = PixelWand::new();
+    pw.set_color("none")?;
+
+    if margins != (0, 0) {
+        mw.set_image_background_color(&pw)?;
+        mw.border_image(&pw, margins.0, margins.1, CompositeOperator::Over)?;
+    }
+
+    let mut smw = MagickWand::new();
+
+    smw.new_image(width, height, &pw)?;
+    smw.set_image_alpha_channel(AlphaChannelOption::Set)?;
+    smw.import_image_pixels(0, 0, width, height, &shadow_pixels, "RGBA")?;
+
+    let canvas = MagickWand::new();
+
+    canvas.new_image(width + margins.0 * 2, height + margins.1 * 2, &pw)?;
+
+    canvas.compose_images(
+        &smw,
+        CompositeOperator::Over,
+        true,
+        margins.0 as isize + offset_x,
+        margins.1 as isize + offset_y,
+    )?;
+
+    if blur > 0f64 {
+        canvas.gaussian_blur_image(0f64, blur)?;
+    }
+
+    mw.compose_images(&canvas, CompositeOperator::DstOver, true, 0, 0)
+}
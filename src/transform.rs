@@ -0,0 +1,174 @@
+use magick_rust::{MagickError, MagickWand, PixelWand};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Rotate and mirror an image. It is applied after the orientation from image metadata and before cropping and resizing.
+///
+/// The operations are applied in this order: `rotate`, `flip`, `flop`, `transpose` and `transverse`.
+pub struct Transform {
+    /// The clockwise rotation angle in degrees.
+    pub rotate:     f64,
+    /// The color used to fill the corners uncovered by a rotation which is not a multiple of 90 degrees, such as `"#FFFFFF"` or `"none"`.
+    pub background: String,
+    /// Crop the largest upright rectangle out of a rotated image, so that no corner has to be filled.
+    pub auto_crop:  bool,
+    /// Mirror the image vertically.
+    pub flip:       bool,
+    /// Mirror the image horizontally.
+    pub flop:       bool,
+    /// Mirror the image along the top-left to bottom-right diagonal.
+    pub transpose:  bool,
+    /// Mirror the image along the bottom-left to top-right diagonal.
+    pub transverse: bool,
+}
+
+impl Transform {
+    /// Create a `Transform` instance with default values.
+    /// ```rust,ignore
+    /// Transform {
+    ///     rotate: 0f64,
+    ///     background: String::from("none"),
+    ///     auto_crop: false,
+    ///     flip: false,
+    ///     flop: false,
+    ///     transpose: false,
+    ///     transverse: false,
+    /// }
+    /// ```
+    #[inline]
+    pub fn new() -> Transform {
+        Transform {
+            rotate:     0f64,
+            background: String::from("none"),
+            auto_crop:  false,
+            flip:       false,
+            flop:       false,
+            transpose:  false,
+            transverse: false,
+        }
+    }
+
+    /// Create a `Transform` instance which rotates an image clockwise.
+    #[inline]
+    pub fn rotate(degrees: f64) -> Transform {
+        Transform {
+            rotate: degrees,
+            ..Transform::new()
+        }
+    }
+
+    #[inline]
+    fn angle(&self) -> f64 {
+        self.rotate.rem_euclid(360f64)
+    }
+
+    /// Whether the rotation is a multiple of 90 degrees.
+    #[inline]
+    pub(crate) fn is_right_angle(&self) -> bool {
+        self.angle() % 90f64 == 0f64
+    }
+
+    /// Whether the width and the height of an image are swapped. It is only meaningful for right angles.
+    #[inline]
+    pub(crate) fn swaps_dimensions(&self) -> bool {
+        let angle = self.angle();
+
+        (angle == 90f64 || angle == 270f64) ^ self.transpose ^ self.transverse
+    }
+
+    /// Apply the transform to an image.
+    pub(crate) fn apply(&self, mw: &MagickWand) -> Result<(), MagickError> {
+        let angle = self.angle();
+
+        if angle != 0f64 {
+            let width = mw.get_image_width() as f64;
+            let height = mw.get_image_height() as f64;
+
+            let mut pw = PixelWand::new();
+            pw.set_color(self.background.as_str())?;
+
+            mw.rotate_image(&pw, angle)?;
+
+            if self.auto_crop && !self.is_right_angle() {
+                let (crop_width, crop_height) = inscribed_size(width, height, angle.to_radians());
+
+                let crop_width = (crop_width.floor() as usize).max(1);
+                let crop_height = (crop_height.floor() as usize).max(1);
+
+                let rotated_width = mw.get_image_width();
+                let rotated_height = mw.get_image_height();
+
+                mw.crop_image(
+                    crop_width,
+                    crop_height,
+                    (rotated_width.saturating_sub(crop_width) / 2) as isize,
+                    (rotated_height.saturating_sub(crop_height) / 2) as isize,
+                )?;
+            }
+
+            mw.reset_image_page("")?;
+        }
+
+        if self.flip {
+            mw.flip_image()?;
+        }
+
+        if self.flop {
+            mw.flop_image()?;
+        }
+
+        // MagickWand has no transpose or transverse, so they are a right-angle rotation followed by a flop
+        if self.transpose || self.transverse {
+            let pw = PixelWand::new();
+
+            if self.transpose {
+                mw.rotate_image(&pw, 90f64)?;
+                mw.flop_image()?;
+            }
+
+            if self.transverse {
+                mw.rotate_image(&pw, 270f64)?;
+                mw.flop_image()?;
+            }
+
+            mw.reset_image_page("")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self {
+        Transform::new()
+    }
+}
+
+/// Compute the size of the largest upright rectangle inside a `width`×`height` rectangle rotated by `angle` radians.
+fn inscribed_size(width: f64, height: f64, angle: f64) -> (f64, f64) {
+    if width <= 0f64 || height <= 0f64 {
+        return (0f64, 0f64);
+    }
+
+    let width_is_longer = width >= height;
+
+    let (long, short) = if width_is_longer { (width, height) } else { (height, width) };
+
+    let sin = angle.sin().abs();
+    let cos = angle.cos().abs();
+
+    if short <= 2f64 * sin * cos * long || (sin - cos).abs() < 1e-10 {
+        // the rectangle touches the longer sides only
+        let x = 0.5 * short;
+
+        if width_is_longer {
+            (x / sin, x / cos)
+        } else {
+            (x / cos, x / sin)
+        }
+    } else {
+        let cos_2a = cos * cos - sin * sin;
+
+        ((width * cos - height * sin) / cos_2a, (height * cos - width * sin) / cos_2a)
+    }
+}
@@ -0,0 +1,112 @@
+use magick_rust::{MagickError, MagickWand};
+
+use crate::functions::parse_color;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Remove uniform borders or transparent edges. It is applied after the transform and before cropping and resizing.
+pub struct Trim {
+    /// From 0 to 1, how much each channel of a pixel can differ from the trimmed color and still be trimmed.
+    pub fuzz:    f64,
+    /// The color to trim, such as `"#FFFFFF"`. `None` means the color of the top-left pixel, and if that pixel is transparent, transparent edges are trimmed.
+    pub color:   Option<String>,
+    /// The padding (in pixels) kept around the trimmed image, as long as the original image is large enough.
+    pub padding: u16,
+}
+
+impl Trim {
+    /// Create a `Trim` instance with default values.
+    /// ```rust,ignore
+    /// Trim {
+    ///     fuzz: 0.1f64,
+    ///     color: None,
+    ///     padding: 0u16,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new() -> Trim {
+        Trim {
+            fuzz: 0.1f64, color: None, padding: 0u16
+        }
+    }
+
+    /// Apply the trim to an image.
+    pub(crate) fn apply(&self, mw: &MagickWand) -> Result<(), MagickError> {
+        let width = mw.get_image_width();
+        let height = mw.get_image_height();
+
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let pixels = match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+            Some(pixels) => pixels,
+            None => return Err("Cannot export the pixels of the image.".into()),
+        };
+
+        let tolerance = (self.fuzz.clamp(0f64, 1f64) * 255f64).round() as u8;
+
+        let target = match self.color.as_ref() {
+            Some(color) => {
+                let [r, g, b] = parse_color(color)?;
+
+                [r, g, b, 255]
+            },
+            None => [pixels[0], pixels[1], pixels[2], pixels[3]],
+        };
+
+        let is_background = |pixel: &[u8]| {
+            if target[3] == 0 {
+                pixel[3] <= tolerance
+            } else {
+                pixel
+                    .iter()
+                    .zip(target)
+                    .all(|(sample, target)| sample.abs_diff(target) <= tolerance)
+            }
+        };
+
+        let mut left = width;
+        let mut right = 0;
+        let mut top = height;
+        let mut bottom = 0;
+
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            if !is_background(pixel) {
+                let x = i % width;
+                let y = i / width;
+
+                left = left.min(x);
+                right = right.max(x + 1);
+                top = top.min(y);
+                bottom = bottom.max(y + 1);
+            }
+        }
+
+        // the whole image would be trimmed, so keep it
+        if left >= right || top >= bottom {
+            return Ok(());
+        }
+
+        let padding = self.padding as usize;
+
+        let left = left.saturating_sub(padding);
+        let top = top.saturating_sub(padding);
+        let right = (right + padding).min(width);
+        let bottom = (bottom + padding).min(height);
+
+        if left == 0 && top == 0 && right == width && bottom == height {
+            return Ok(());
+        }
+
+        mw.crop_image(right - left, bottom - top, left as isize, top as isize)?;
+
+        mw.reset_image_page("")
+    }
+}
+
+impl Default for Trim {
+    #[inline]
+    fn default() -> Self {
+        Trim::new()
+    }
+}
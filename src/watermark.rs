@@ -0,0 +1,287 @@
+use std::{fs, ptr};
+
+use magick_rust::{AlphaChannelOption, CompositeOperator, FilterType, MagickError, MagickWand};
+
+use crate::{
+    compute_output_size,
+    functions::{fetch_magic_wand_inner, fetch_trusted_magic_wand},
+    image_config::ResizeConfig,
+    AsImageInput, ImageInput, ImageResource,
+};
+
+/// The position of a watermark.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatermarkGravity {
+    NorthWest,
+    North,
+    NorthEast,
+    West,
+    Center,
+    East,
+    SouthWest,
+    South,
+    SouthEast,
+}
+
+/// The blend mode used to draw a watermark.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatermarkBlend {
+    Over,
+    Multiply,
+    Screen,
+    Overlay,
+    SoftLight,
+}
+
+impl WatermarkBlend {
+    #[inline]
+    fn composite_operator(self) -> CompositeOperator {
+        match self {
+            Self::Over => CompositeOperator::Over,
+            Self::Multiply => CompositeOperator::Multiply,
+            Self::Screen => CompositeOperator::Screen,
+            Self::Overlay => CompositeOperator::Overlay,
+            Self::SoftLight => CompositeOperator::SoftLight,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A watermark drawn on an output image after it is resized.
+pub struct Watermark {
+    /// The watermark image. An SVG image is rasterized at the size it is drawn, so it stays crisp. It is trusted, so the read policy of the `ImageConvert` runtime does not apply to it.
+    pub image:   ImageResource,
+    /// The position of the watermark. It is ignored if `tiled` is `true`.
+    pub gravity: WatermarkGravity,
+    /// The horizontal and vertical margins (in pixels). In the tiled mode, they are the gaps between tiles.
+    pub margin:  (u16, u16),
+    /// The width of the watermark relative to the width of the output image, from 0 to 1. `0` means the original size of the watermark image.
+    pub scale:   f64,
+    /// From 0 to 1, the opacity of the watermark.
+    pub opacity: f64,
+    /// Repeat the watermark over the whole output image.
+    pub tiled:   bool,
+    /// The blend mode.
+    pub blend:   WatermarkBlend,
+}
+
+impl Watermark {
+    /// Create a `Watermark` instance with default values.
+    /// ```rust,ignore
+    /// Watermark {
+    ///     image,
+    ///     gravity: WatermarkGravity::SouthEast,
+    ///     margin: (16u16, 16u16),
+    ///     scale: 0.25f64,
+    ///     opacity: 1f64,
+    ///     tiled: false,
+    ///     blend: WatermarkBlend::Over,
+    /// }
+    /// ```
+    #[inline]
+    pub const fn new(image: ImageResource) -> Watermark {
+        Watermark {
+            image,
+            gravity: WatermarkGravity::SouthEast,
+            margin: (16u16, 16u16),
+            scale: 0.25f64,
+            opacity: 1f64,
+            tiled: false,
+            blend: WatermarkBlend::Over,
+        }
+    }
+}
+
+/// A watermark image decoded once, and prepared at the widths it is drawn.
+#[derive(Debug)]
+struct DecodedWatermark {
+    /// The address of the watermark, which identifies it during the call.
+    watermark: *const Watermark,
+    /// The watermark image at its original size.
+    source:    MagickWand,
+    /// The source of an SVG watermark image, which is rasterized again when it is enlarged, so that it stays crisp.
+    svg:       Option<String>,
+    /// The prepared watermark images and the widths of the images they are drawn on.
+    prepared:  Vec<(usize, MagickWand)>,
+}
+
+impl DecodedWatermark {
+    fn decode(watermark: &Watermark) -> Result<DecodedWatermark, MagickError> {
+        let config = ResizeConfig {
+            width: 0, height: 0, crop: None
+        };
+
+        let (source, _) = fetch_trusted_magic_wand(&watermark.image, &config)?;
+
+        let svg = match source.get_image_format()?.as_str() {
+            "SVG" | "MVG" => match watermark.image.as_image_input()? {
+                ImageInput::Path(p) => fs::read_to_string(p).ok(),
+                ImageInput::Data(b) => String::from_utf8(b.to_vec()).ok(),
+                ImageInput::MagickWand(_) => None,
+            },
+            _ => None,
+        };
+
+        Ok(DecodedWatermark {
+            watermark: watermark as *const Watermark,
+            source,
+            svg,
+            prepared: Vec::new(),
+        })
+    }
+
+    /// Get the watermark image at the size it is drawn on an image whose width is `width`.
+    fn prepare(&mut self, watermark: &Watermark, width: usize) -> Result<&MagickWand, MagickError> {
+        if let Some(index) = self.prepared.iter().position(|(w, _)| *w == width) {
+            return Ok(&self.prepared[index].1);
+        }
+
+        let scale = watermark.scale.clamp(0f64, 1f64);
+
+        let target_width = (width as f64 * scale).round().min(f64::from(u16::MAX)) as u16;
+
+        let original_width = self.source.get_image_width() as u16;
+        let original_height = self.source.get_image_height() as u16;
+
+        let mut mw = self.source.clone();
+
+        if target_width > 0 {
+            if let Some((width, height)) =
+                compute_output_size(false, original_width, original_height, target_width, 0)
+            {
+                let rasterized = match self.svg.as_ref() {
+                    Some(svg) if width >= original_width => {
+                        let (rasterized, vector) =
+                            fetch_magic_wand_inner(mw, width, height, svg.clone())?;
+
+                        mw = rasterized;
+
+                        vector
+                    },
+                    _ => false,
+                };
+
+                if !rasterized {
+                    mw.resize_image(width as usize, height as usize, FilterType::Lanczos)?;
+                }
+            }
+        }
+
+        mw.set_image_alpha_channel(AlphaChannelOption::Set)?;
+
+        let opacity = watermark.opacity.clamp(0f64, 1f64);
+
+        if opacity < 1f64 {
+            let width = mw.get_image_width();
+            let height = mw.get_image_height();
+
+            let mut pixels = match mw.export_image_pixels(0, 0, width, height, "RGBA") {
+                Some(pixels) => pixels,
+                None => return Err("Cannot export the pixels of the watermark.".into()),
+            };
+
+            for alpha in pixels.iter_mut().skip(3).step_by(4) {
+                *alpha = (f64::from(*alpha) * opacity).round() as u8;
+            }
+
+            mw.import_image_pixels(0, 0, width, height, &pixels, "RGBA")?;
+        }
+
+        self.prepared.push((width, mw));
+
+        Ok(&self.prepared[self.prepared.len() - 1].1)
+    }
+}
+
+/// The watermark images decoded during one conversion call, so that a watermark is decoded only once even if it is drawn on many images, such as the entries of an ICO image or the outputs of a `DerivativeSet`. The configs are borrowed during the call, so a watermark is identified by its address.
+#[derive(Debug, Default)]
+pub(crate) struct WatermarkCache {
+    decoded: Vec<DecodedWatermark>,
+}
+
+impl WatermarkCache {
+    #[inline]
+    pub(crate) const fn new() -> WatermarkCache {
+        WatermarkCache {
+            decoded: Vec::new()
+        }
+    }
+
+    /// Get a watermark image at the size it is drawn on an image whose width is `width`.
+    fn fetch(&mut self, watermark: &Watermark, width: usize) -> Result<&MagickWand, MagickError> {
+        let index = match self.decoded.iter().position(|d| ptr::eq(d.watermark, watermark)) {
+            Some(index) => index,
+            None => {
+                self.decoded.push(DecodedWatermark::decode(watermark)?);
+
+                self.decoded.len() - 1
+            },
+        };
+
+        self.decoded[index].prepare(watermark, width)
+    }
+}
+
+/// Draw a watermark on an image.
+pub(crate) fn apply_watermark(
+    mw: &MagickWand,
+    watermark: &Watermark,
+    watermarks: &mut WatermarkCache,
+) -> Result<(), MagickError> {
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let wmw = watermarks.fetch(watermark, width)?;
+
+    let watermark_width = wmw.get_image_width() as isize;
+    let watermark_height = wmw.get_image_height() as isize;
+
+    let width = width as isize;
+    let height = height as isize;
+    let margin_x = watermark.margin.0 as isize;
+    let margin_y = watermark.margin.1 as isize;
+
+    let operator = watermark.blend.composite_operator();
+
+    if watermark.tiled {
+        let step_x = (watermark_width + margin_x).max(1);
+        let step_y = (watermark_height + margin_y).max(1);
+
+        let mut y = 0;
+
+        while y < height {
+            let mut x = 0;
+
+            while x < width {
+                mw.compose_images(wmw, operator, true, x, y)?;
+
+                x += step_x;
+            }
+
+            y += step_y;
+        }
+
+        return Ok(());
+    }
+
+    let left = margin_x;
+    let center_x = (width - watermark_width) / 2;
+    let right = width - watermark_width - margin_x;
+    let top = margin_y;
+    let center_y = (height - watermark_height) / 2;
+    let bottom = height - watermark_height - margin_y;
+
+    let (x, y) = match watermark.gravity {
+        WatermarkGravity::NorthWest => (left, top),
+        WatermarkGravity::North => (center_x, top),
+        WatermarkGravity::NorthEast => (right, top),
+        WatermarkGravity::West => (left, center_y),
+        WatermarkGravity::Center => (center_x, center_y),
+        WatermarkGravity::East => (right, center_y),
+        WatermarkGravity::SouthWest => (left, bottom),
+        WatermarkGravity::South => (center_x, bottom),
+        WatermarkGravity::SouthEast => (right, bottom),
+    };
+
+    mw.compose_images(wmw, operator, true, x, y)
+}
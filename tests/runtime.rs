@@ -0,0 +1,95 @@
+use image_convert::{
+    identify_ping, identify_read, is_limit_exceeded, to_png, GlobalOptions, ImageConvert,
+    ImageResource, PNGConfig, ReadPolicy, ResourceLimits, Watermark,
+};
+
+const INPUT_IMAGE_PATH: &str = r"tests/data/dropbox.svg";
+const JPEG_IMAGE_PATH: &str = r"tests/data/landscape.jpg";
+
+// the runtime is process-wide, so it is tested in its own test binary (a separate process from tests.rs and vector.rs), and everything is tested in one test function
+#[test]
+fn runtime_lifecycle() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut options = GlobalOptions::new();
+
+    options.threads = 1;
+    options.temporary_path = Some(std::env::temp_dir());
+    options.read_policy = Some(ReadPolicy::web());
+
+    let mut runtime = ImageConvert::init(options).unwrap();
+
+    assert!(ImageConvert::init(GlobalOptions::new()).is_err());
+
+    // SVG is not allowed by the default read policy of the runtime
+    assert!(identify_ping(&input).is_err());
+
+    let mut output = ImageResource::with_capacity(0);
+
+    assert!(to_png(&mut output, &input, &PNGConfig::new()).is_err());
+
+    // a config can still set its own read policy
+    let mut policy = ReadPolicy::new();
+
+    policy.forbid_delegates = false;
+
+    let mut config = PNGConfig::new();
+
+    config.read_policy = Some(policy.clone());
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    // a watermark is trusted, so it can be an SVG image
+    let mut config = PNGConfig::new();
+
+    config.watermark = Some(Watermark::new(ImageResource::from_path(INPUT_IMAGE_PATH)));
+
+    to_png(&mut output, &ImageResource::from_path(JPEG_IMAGE_PATH), &config).unwrap();
+
+    assert_eq!(1, runtime.options().threads);
+
+    // the conversion methods use the default configs of the runtime
+    runtime.configs_mut().png.width = 64;
+    runtime.configs_mut().png.read_policy = Some(policy);
+
+    let mut output = ImageResource::with_capacity(0);
+
+    runtime.to_png(&mut output, &input).unwrap();
+
+    assert_eq!(64, runtime.identify_ping(&output).unwrap().resolution.width);
+
+    drop(runtime);
+
+    // MagickWand has been terminated, so the temporary path can be set again
+    let mut options = GlobalOptions::new();
+
+    let mut limits = ResourceLimits::new();
+
+    limits.max_width = 256;
+
+    options.temporary_path = Some(std::env::temp_dir());
+    options.resource_limits = Some(limits);
+
+    let runtime = ImageConvert::init(options).unwrap();
+
+    // identification falls back to the resource limits of the runtime
+    let err = identify_read(&mut None, &input).unwrap_err();
+
+    assert!(is_limit_exceeded(&err));
+
+    drop(runtime);
+
+    // MagickWand is initialized implicitly again, without the limits
+    let id = identify_ping(&input).unwrap();
+
+    assert_eq!(512, id.resolution.width);
+
+    identify_read(&mut None, &input).unwrap();
+
+    // the temporary path cannot be set after MagickWand is initialized implicitly
+    let mut options = GlobalOptions::new();
+
+    options.temporary_path = Some(std::env::temp_dir());
+
+    assert!(ImageConvert::init(options).is_err());
+}
@@ -1,13 +1,182 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
 use image_convert::{
-    identify_ping, to_bmp, to_gif, to_gray_raw, to_ico, to_jpg, to_pgm, to_png, to_tiff, to_webp,
-    BMPConfig, GIFConfig, GrayRawConfig, ICOConfig, ImageResource, InterlaceType, JPGConfig,
-    PGMConfig, PNGConfig, TIFFConfig, WEBPConfig,
+    average_color, blurhash, blurhash_with_config, decode_blurhash, decode_thumbhash,
+    dominant_colors, hamming_distance, identify_ping, identify_read, is_limit_exceeded,
+    magick_rust::{ColorspaceType, MagickWand},
+    perceptual_hash, thumbhash, to_bmp, to_gif, to_gray_raw, to_ico, to_jpg, to_pgm, to_png,
+    to_tiff, to_webp, Adjustments, BMPConfig, ChromaKey, ColorName, ColorTone, Crop, DerivativeSet,
+    Effect, GIFConfig, GrayRawConfig, HashKind, ICOConfig, ImageResource, InterlaceType, JPGConfig,
+    Luma, Lut, LutSource, MagickError, OutputConfig, PGMConfig, PNGConfig, Placeholder,
+    PlaceholderKind, PlaceholderSink, ResizeMode, ResourceLimits, Shadow, Shape, ShapeMask,
+    TIFFConfig, Transform, Trim, WEBPConfig, Watermark, WatermarkGravity,
 };
 
 const INPUT_IMAGE_PATH: &str = r"tests/data/P1060382.JPG";
 
+const IDENTITY_CUBE_PATH: &str = r"tests/data/identity.cube";
+const INVERT_CUBE_PATH: &str = r"tests/data/invert.cube";
+const HALD_IDENTITY_PATH: &str = r"tests/data/hald_identity.png";
+const LANDSCAPE_IMAGE_PATH: &str = r"tests/data/landscape.jpg";
+const DEEP_COLOR_IMAGE_PATH: &str = r"tests/data/deep_color.png";
+const BOMB_IMAGE_PATH: &str = r"tests/data/bomb.png";
+const RIGHT_TOP_IMAGE_PATH: &str = r"tests/data/right_top.tiff";
+const GRADIENT_IMAGE_PATH: &str = r"tests/data/gradient.png";
+const RED_IMAGE_PATH: &str = r"tests/data/red.png";
+const RED_SQUARE_IMAGE_PATH: &str = r"tests/data/red_square.png";
+const TRIM_WHITE_IMAGE_PATH: &str = r"tests/data/trim_white.png";
+const TRIM_TRANSPARENT_IMAGE_PATH: &str = r"tests/data/trim_transparent.png";
+const CHROMA_KEY_IMAGE_PATH: &str = r"tests/data/chroma_key.png";
+const PALETTE_IMAGE_PATH: &str = r"tests/data/palette.png";
+const GRAY_IMAGE_PATH: &str = r"tests/data/gray.png";
+const RED_256_IMAGE_PATH: &str = r"tests/data/red_256.png";
+const WHITE_DOT_IMAGE_PATH: &str = r"tests/data/white_dot.png";
+
+const HASH_KINDS: [HashKind; 4] =
+    [HashKind::Average, HashKind::Difference, HashKind::Perceptual, HashKind::Wavelet];
+
+const SHAPE_SIZE: usize = 32;
+
+/// The width of the gradient image.
+const GRADIENT_WIDTH: usize = 16;
+/// The height of the gradient image.
+const GRADIENT_HEIGHT: usize = 8;
+
+/// Get the alpha values at (0, 0), (4, 4) and (8, 8) of a 16x16 green image with a red pixel at (4, 4) and a dark green pixel at (8, 8), after keying it.
+fn chroma_key_alpha(chroma_key: ChromaKey, alpha_threshold: Option<u8>) -> [u8; 3] {
+    let input = ImageResource::from_path(CHROMA_KEY_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.chroma_key = Some(chroma_key);
+    config.alpha_threshold = alpha_threshold;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let alpha = mw.export_image_pixels(0, 0, 16, 16, "A").unwrap();
+
+    [alpha[0], alpha[4 * 16 + 4], alpha[8 * 16 + 8]]
+}
+
+/// Compute the FNV-1a hash of the RGB samples of the 16x8 gradient image, whose pixel at (x, y) is (x * 16, y * 32, (x + y) * 8), after applying effects.
+fn effects_hash(effects: Vec<Effect>) -> u64 {
+    let mut config = PNGConfig::new();
+
+    config.effects = effects;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(GRADIENT_IMAGE_PATH), &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let pixels = mw.export_image_pixels(0, 0, GRADIENT_WIDTH, GRADIENT_HEIGHT, "RGB").unwrap();
+
+    pixels.iter().fold(0xCBF29CE484222325, |hash, sample| {
+        (hash ^ u64::from(*sample)).wrapping_mul(0x100000001B3)
+    })
+}
+
+#[inline]
+fn cube(path: &str) -> LutSource {
+    LutSource::Cube(fs::read_to_string(path).unwrap())
+}
+
+/// Get the RGB samples of the gradient image after applying a LUT and effects.
+fn lut_pixels(lut: Option<Lut>, effects: Vec<Effect>) -> Result<Vec<u8>, MagickError> {
+    let mut config = PNGConfig::new();
+
+    config.lut = lut;
+    config.effects = effects;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(GRADIENT_IMAGE_PATH), &config)?;
+
+    let mw = output.into_magick_wand().unwrap();
+
+    Ok(mw.export_image_pixels(0, 0, GRADIENT_WIDTH, GRADIENT_HEIGHT, "RGB").unwrap())
+}
+
+/// Get the RGB samples of an image after adjusting it.
+fn adjusted_rgb(
+    input_path: &str,
+    adjustments: Option<Adjustments>,
+) -> Result<Vec<u8>, MagickError> {
+    let mut config = PNGConfig::new();
+
+    config.adjustments = adjustments;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(input_path), &config)?;
+
+    let mw = output.into_magick_wand().unwrap();
+
+    Ok(mw.export_image_pixels(0, 0, mw.get_image_width(), mw.get_image_height(), "RGB").unwrap())
+}
+
+/// Compute the mean of each channel of RGB samples.
+fn channel_means(pixels: &[u8]) -> [f64; 3] {
+    let mut sums = [0f64; 3];
+
+    for pixel in pixels.chunks_exact(3) {
+        for (sum, sample) in sums.iter_mut().zip(pixel) {
+            *sum += f64::from(*sample);
+        }
+    }
+
+    sums.map(|sum| sum / (pixels.len() / 3) as f64)
+}
+
+/// Get the samples of a pixel from the RGBA pixels of an image.
+#[inline]
+fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+    let i = (y * width + x) * 4;
+
+    &pixels[i..i + 4]
+}
+
+/// Shape a 32x32 red image and get its size and RGBA pixels.
+fn shape_rgba(shape: Shape) -> (usize, usize, Vec<u8>) {
+    let mut config = PNGConfig::new();
+
+    config.shape = Some(shape);
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(RED_SQUARE_IMAGE_PATH), &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    (width, height, mw.export_image_pixels(0, 0, width, height, "RGBA").unwrap())
+}
+
+/// Get the output size of a 32x24 image with an 8x6 red block at (10, 5), after trimming and cropping it.
+fn trim_size(input_path: &str, trim: Trim, crop: Option<Crop>) -> (u32, u32) {
+    let input = ImageResource::from_path(input_path);
+
+    let mut config = PNGConfig::new();
+
+    config.trim = Some(trim);
+    config.crop = crop;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let id = identify_ping(&output.into_vec().unwrap()).unwrap();
+
+    (id.resolution.width, id.resolution.height)
+}
+
 #[test]
 fn get_identify() {
     let input = ImageResource::from_path(INPUT_IMAGE_PATH);
@@ -145,6 +314,18 @@ fn to_ico_file2file() {
     to_ico(&mut output, &input, &config).unwrap();
 }
 
+#[test]
+fn to_ico_padding_too_large() {
+    // the photo is larger than 256 px, so it cannot be an entry at its original size
+    let mut config = ICOConfig::new();
+
+    config.size.push((0u16, 0u16));
+
+    let mut output = ImageResource::with_capacity(0);
+
+    assert!(to_ico(&mut output, &ImageResource::from_path(INPUT_IMAGE_PATH), &config).is_err());
+}
+
 #[test]
 fn to_gray_file2file() {
     let source_image_path = Path::new(INPUT_IMAGE_PATH);
@@ -178,3 +359,791 @@ fn to_pgm_file2file() {
 
     to_pgm(&mut output, &input, &config).unwrap();
 }
+
+#[test]
+fn chroma_key() {
+    let mut chroma_key = ChromaKey::new("#00FF00");
+
+    chroma_key.feather = 0f64;
+
+    assert_eq!([0, 255, 255], chroma_key_alpha(chroma_key.clone(), None));
+
+    chroma_key.fuzz = 0.2f64;
+
+    assert_eq!([0, 255, 0], chroma_key_alpha(chroma_key, None));
+}
+
+#[test]
+fn chroma_key_feather() {
+    let mut chroma_key = ChromaKey::new("#00FF00");
+
+    chroma_key.fuzz = 0.1f64;
+    chroma_key.feather = 0.1f64;
+
+    let [background, foreground, edge] = chroma_key_alpha(chroma_key.clone(), None);
+
+    assert_eq!(0, background);
+    assert_eq!(255, foreground);
+    assert!(edge > 0 && edge < 255);
+
+    assert_eq!([0, 255, 0], chroma_key_alpha(chroma_key.clone(), Some(128)));
+    assert_eq!([0, 255, 255], chroma_key_alpha(chroma_key, Some(64)));
+}
+
+#[test]
+fn effects_checksum() {
+    assert_eq!(0xC65143D58BC92725, effects_hash(Vec::new()));
+    assert_eq!(0x97E4DFD4B6BC03A7, effects_hash(vec![Effect::Grayscale(Luma::Rec709)]));
+    assert_eq!(0xE6F02AFBF70DA4CE, effects_hash(vec![Effect::Grayscale(Luma::Rec601)]));
+    assert_eq!(0x609E89D8C01833F5, effects_hash(vec![Effect::Grayscale(Luma::Average)]));
+    assert_eq!(0xBE3A0C7A18E03E8D, effects_hash(vec![Effect::Sepia(1f64)]));
+    assert_eq!(0x83CCFE5A1B77CE53, effects_hash(vec![Effect::Sepia(0.5f64)]));
+    assert_eq!(0x8AFAAF0D9114C2C5, effects_hash(vec![Effect::Pixelate(3)]));
+    assert_eq!(
+        0x01F206BE7CFC1F89,
+        effects_hash(vec![Effect::Duotone(String::from("#000080"), String::from("#FFFF00"))])
+    );
+    assert_eq!(0xA90E45787972B0A2, effects_hash(vec![Effect::Posterize(4)]));
+    assert_eq!(0x3D4FBB9B455A7CAA, effects_hash(vec![Effect::Vignette(0.5f64)]));
+    assert_eq!(0xF67E6215CCE3A125, effects_hash(vec![Effect::Invert]));
+}
+
+#[test]
+fn effects_in_order() {
+    assert_eq!(
+        0x3F7B3A662057199F,
+        effects_hash(vec![Effect::Grayscale(Luma::Rec601), Effect::Posterize(4), Effect::Invert])
+    );
+
+    assert_eq!(effects_hash(Vec::new()), effects_hash(vec![Effect::Invert, Effect::Invert]));
+}
+
+#[test]
+fn effects_deep_color() {
+    let input = MagickWand::new();
+
+    input.read_image(DEEP_COLOR_IMAGE_PATH).unwrap();
+
+    let (width, height) = (input.get_image_width(), input.get_image_height());
+
+    let original = input.export_image_pixels_double(0, 0, width, height, "RGB").unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.effects = vec![Effect::Invert];
+
+    // the 16-bit samples are not quantized to 8 bits
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let inverted = mw.export_image_pixels_double(0, 0, width, height, "RGB").unwrap();
+
+    for (original, inverted) in original.iter().zip(inverted) {
+        assert!((1f64 - original - inverted).abs() < 1e-4);
+    }
+
+    // a CMYK image is converted to sRGB before its samples are modified
+    let cmyk = input.clone();
+
+    cmyk.transform_image_colorspace(ColorspaceType::CMYK).unwrap();
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &cmyk, &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    assert_eq!(ColorspaceType::sRGB, mw.get_image_colorspace());
+
+    let inverted = mw.export_image_pixels_double(0, 0, width, height, "RGB").unwrap();
+
+    for (original, inverted) in original.iter().zip(inverted) {
+        assert!((1f64 - original - inverted).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn effects_blur() {
+    let blurred = effects_hash(vec![Effect::Blur(1.5f64)]);
+
+    assert_ne!(effects_hash(Vec::new()), blurred);
+    assert_eq!(blurred, effects_hash(vec![Effect::Blur(1.5f64)]));
+}
+
+#[test]
+fn lut_cube() {
+    let original = lut_pixels(None, Vec::new()).unwrap();
+
+    let identity = Lut::new(cube(IDENTITY_CUBE_PATH)).unwrap();
+
+    assert_eq!(original, lut_pixels(Some(identity), Vec::new()).unwrap());
+
+    let invert = Lut::new(cube(INVERT_CUBE_PATH)).unwrap();
+
+    assert_eq!(
+        lut_pixels(None, vec![Effect::Invert]).unwrap(),
+        lut_pixels(Some(invert), Vec::new()).unwrap()
+    );
+
+    let mut invert = Lut::new(cube(INVERT_CUBE_PATH)).unwrap();
+
+    invert.strength = 0f64;
+
+    assert_eq!(original, lut_pixels(Some(invert), Vec::new()).unwrap());
+}
+
+#[test]
+fn lut_hald() {
+    let hald = LutSource::Hald(ImageResource::from_path(HALD_IDENTITY_PATH));
+
+    assert_eq!(
+        lut_pixels(None, Vec::new()).unwrap(),
+        lut_pixels(Some(Lut::new(hald).unwrap()), Vec::new()).unwrap()
+    );
+}
+
+#[test]
+fn lut_input_range() {
+    let cube = fs::read_to_string(IDENTITY_CUBE_PATH)
+        .unwrap()
+        .replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 2");
+
+    let halved = Lut::new(LutSource::Cube(cube)).unwrap();
+
+    let expected: Vec<u8> = lut_pixels(None, Vec::new())
+        .unwrap()
+        .into_iter()
+        .map(|v| (f64::from(v) / 2f64).round() as u8)
+        .collect();
+
+    assert_eq!(expected, lut_pixels(Some(halved), Vec::new()).unwrap());
+
+    assert!(Lut::new(LutSource::Cube(String::from("LUT_3D_INPUT_RANGE 0\n"))).is_err());
+}
+
+#[test]
+fn lut_unknown_keywords() {
+    let cube = fs::read_to_string(IDENTITY_CUBE_PATH)
+        .unwrap()
+        .replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_IN_VIDEO_RANGE\nLUT_1D_INPUT_RANGE 0 1");
+
+    let identity = Lut::new(LutSource::Cube(cube)).unwrap();
+
+    assert_eq!(
+        lut_pixels(None, Vec::new()).unwrap(),
+        lut_pixels(Some(identity), Vec::new()).unwrap()
+    );
+}
+
+#[test]
+fn lut_validation() {
+    assert!(Lut::new(LutSource::Cube(String::from("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n"))).is_err());
+
+    assert!(Lut::new(LutSource::Cube(String::from("LUT_3D_SIZE 512\n"))).is_err());
+
+    // a forged size does not allocate a table before the entries are parsed
+    assert!(Lut::new(LutSource::Cube(String::from("LUT_3D_SIZE 128\n0 0 0\n"))).is_err());
+
+    // too many entries
+    let cube = fs::read_to_string(IDENTITY_CUBE_PATH).unwrap() + "0 0 0\n";
+
+    assert!(Lut::new(LutSource::Cube(cube)).is_err());
+
+    // not a square
+    let hald = ImageResource::from_path(RED_IMAGE_PATH);
+
+    assert!(Lut::new(LutSource::Hald(hald)).is_err());
+}
+
+#[test]
+fn palette_dominant_colors() {
+    // the left three quarters are red and the right quarter is blue
+    let colors = dominant_colors(&ImageResource::from_path(PALETTE_IMAGE_PATH), 3).unwrap();
+
+    assert_eq!(2, colors.len());
+
+    assert_eq!([255, 0, 0, 255], colors[0].rgba);
+    assert_eq!(0.75, colors[0].share);
+    assert_eq!(Some(ColorName::Red), colors[0].color_name);
+    assert_eq!(ColorTone::Vibrant, colors[0].tone);
+
+    assert_eq!([0, 0, 255, 255], colors[1].rgba);
+    assert_eq!(0.25, colors[1].share);
+    assert_eq!(Some(ColorName::Blue), colors[1].color_name);
+
+    let colors = dominant_colors(&ImageResource::from_path(PALETTE_IMAGE_PATH), 1).unwrap();
+
+    assert_eq!(1, colors.len());
+    assert_eq!([191, 0, 64, 255], colors[0].rgba);
+    assert_eq!(1f64, colors[0].share);
+}
+
+#[test]
+fn palette_average_color() {
+    let color = average_color(&ImageResource::from_path(PALETTE_IMAGE_PATH)).unwrap();
+
+    assert_eq!([191, 0, 64, 255], color.rgba);
+    assert_eq!(Some(ColorName::Red), color.color_name);
+    assert_eq!(ColorTone::Vibrant, color.tone);
+
+    let color = average_color(&ImageResource::from_path(GRAY_IMAGE_PATH)).unwrap();
+
+    assert_eq!([128, 128, 128, 255], color.rgba);
+    assert_eq!(None, color.color_name);
+    assert_eq!(ColorTone::Muted, color.tone);
+}
+
+#[test]
+fn perceptual_hash_recompressed() {
+    let jpeg = fs::read(LANDSCAPE_IMAGE_PATH).unwrap();
+
+    let mut config = WEBPConfig::new();
+
+    config.width = 160;
+    config.quality = 60;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_webp(&mut output, &jpeg, &config).unwrap();
+
+    let webp = output.into_vec().unwrap();
+
+    for kind in HASH_KINDS {
+        let a = perceptual_hash(&jpeg, kind).unwrap();
+
+        assert_eq!(a, perceptual_hash(&jpeg, kind).unwrap());
+
+        let b = perceptual_hash(&webp, kind).unwrap();
+
+        assert!(hamming_distance(a, b) <= 10, "{kind:?}: {}", hamming_distance(a, b));
+    }
+}
+
+#[test]
+fn perceptual_hash_different() {
+    let jpeg = fs::read(LANDSCAPE_IMAGE_PATH).unwrap();
+
+    let mut config = JPGConfig::new();
+
+    config.effects = vec![Effect::Invert];
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_jpg(&mut output, &jpeg, &config).unwrap();
+
+    let inverted = output.into_vec().unwrap();
+
+    for kind in [HashKind::Average, HashKind::Perceptual] {
+        let a = perceptual_hash(&jpeg, kind).unwrap();
+        let b = perceptual_hash(&inverted, kind).unwrap();
+
+        assert!(hamming_distance(a, b) > 24, "{kind:?}: {}", hamming_distance(a, b));
+    }
+}
+
+#[test]
+fn hamming_distance_bits() {
+    assert_eq!(0, hamming_distance(0x0123_4567_89AB_CDEF, 0x0123_4567_89AB_CDEF));
+    assert_eq!(64, hamming_distance(0, u64::MAX));
+    assert_eq!(3, hamming_distance(0b1011, 0b0110));
+}
+
+#[test]
+fn placeholder_blurhash() {
+    let input = ImageResource::from_path(RED_IMAGE_PATH);
+
+    let hash = blurhash(&input, 1, 1).unwrap();
+
+    assert_eq!("00TI:j", hash);
+
+    let mw = decode_blurhash(&hash, 8, 4).unwrap().into_magick_wand().unwrap();
+
+    assert_eq!(8, mw.get_image_width());
+    assert_eq!(4, mw.get_image_height());
+
+    let pixels = mw.export_image_pixels(0, 0, 8, 4, "RGB").unwrap();
+
+    assert!(pixels.chunks_exact(3).all(|p| p == [255, 0, 0]));
+
+    assert_eq!(4 + 2 * 4 * 3, blurhash(&input, 4, 3).unwrap().len());
+    assert!(blurhash(&input, 0, 3).is_err());
+
+    // the config prepares the image, so the center square of the gradient image has another hash
+    let input = ImageResource::from_path(GRADIENT_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.crop = Some(Crop::Center(1f64, 1f64));
+
+    assert_eq!(
+        blurhash(&input, 4, 3).unwrap(),
+        blurhash_with_config(&input, &PNGConfig::new(), 4, 3).unwrap()
+    );
+    assert_ne!(
+        blurhash(&input, 4, 3).unwrap(),
+        blurhash_with_config(&input, &config, 4, 3).unwrap()
+    );
+
+    // the DC component cannot exceed 24 bits
+    assert!(decode_blurhash("00~~~~", 8, 4).is_err());
+}
+
+#[test]
+fn placeholder_thumbhash() {
+    let hash = thumbhash(&ImageResource::from_path(RED_IMAGE_PATH)).unwrap();
+
+    let mw = decode_thumbhash(&hash).unwrap().into_magick_wand().unwrap();
+
+    // the aspect ratio is approximated
+    assert_eq!(32, mw.get_image_width());
+    assert_eq!(18, mw.get_image_height());
+
+    let pixels = mw.export_image_pixels(0, 0, 32, 18, "RGBA").unwrap();
+
+    assert!(pixels
+        .chunks_exact(4)
+        .all(|p| { p[0] >= 250 && p[1] <= 4 && p[2] <= 4 && p[3] == 255 }));
+}
+
+#[test]
+fn placeholder_sink() {
+    let input = ImageResource::from_path(RED_IMAGE_PATH);
+
+    let sink = PlaceholderSink::new(PlaceholderKind::BlurHash {
+        components_x: 4, components_y: 3
+    });
+
+    let mut config = PNGConfig::new();
+
+    config.placeholder = Some(sink.clone());
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    assert_eq!(Some(Placeholder::BlurHash(blurhash(&input, 4, 3).unwrap())), sink.take());
+    assert_eq!(None, sink.take());
+}
+
+#[test]
+fn shape_masks() {
+    let (width, height, pixels) = shape_rgba(Shape::new(ShapeMask::Circle));
+
+    assert_eq!((SHAPE_SIZE, SHAPE_SIZE), (width, height));
+    assert_eq!(0, pixel(&pixels, width, 0, 0)[3]);
+    assert_eq!(&[255, 0, 0, 255], pixel(&pixels, width, SHAPE_SIZE / 2, SHAPE_SIZE / 2));
+
+    let (width, _, pixels) = shape_rgba(Shape::new(ShapeMask::RoundedRectangle(8f64)));
+
+    assert_eq!(0, pixel(&pixels, width, 0, 0)[3]);
+    assert_eq!(255, pixel(&pixels, width, SHAPE_SIZE / 2, 0)[3]);
+
+    let (width, _, pixels) = shape_rgba(Shape::new(ShapeMask::Squircle));
+
+    assert_eq!(0, pixel(&pixels, width, 0, 0)[3]);
+    assert_eq!(255, pixel(&pixels, width, SHAPE_SIZE / 2, SHAPE_SIZE / 2)[3]);
+}
+
+#[test]
+fn shape_border_and_shadow() {
+    let mut shape = Shape::new(ShapeMask::RoundedRectangle(4f64));
+
+    shape.border_width = 2f64;
+    shape.border_color = String::from("#0000FF");
+
+    let (width, _, pixels) = shape_rgba(shape.clone());
+
+    assert_eq!(&[0, 0, 255, 255], pixel(&pixels, width, SHAPE_SIZE / 2, 0));
+    assert_eq!(&[255, 0, 0, 255], pixel(&pixels, width, SHAPE_SIZE / 2, SHAPE_SIZE / 2));
+
+    let mut shadow = Shadow::new();
+
+    shadow.blur = 2f64;
+    shadow.offset = (0, 2);
+    shadow.opacity = 1f64;
+
+    shape.shadow = Some(shadow);
+
+    // the margin of the shadow is 2 * 3 + 2 = 8 pixels, reserved inside the requested size
+    let (width, height, pixels) = shape_rgba(shape);
+
+    assert_eq!((SHAPE_SIZE, SHAPE_SIZE), (width, height));
+    assert_eq!(0, pixel(&pixels, width, 0, 0)[3]);
+    assert_eq!(&[0, 0, 255, 255], pixel(&pixels, width, SHAPE_SIZE / 2, 8));
+    assert_eq!(&[255, 0, 0, 255], pixel(&pixels, width, SHAPE_SIZE / 2, SHAPE_SIZE / 2));
+
+    let below = pixel(&pixels, width, SHAPE_SIZE / 2, SHAPE_SIZE - 8 + 1);
+
+    assert_eq!(&[0, 0, 0], &below[..3]);
+    assert!(below[3] > 0);
+}
+
+#[test]
+fn shape_jpg() {
+    let mut config = JPGConfig::new();
+
+    config.shape = Some(Shape::new(ShapeMask::Circle));
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_jpg(&mut output, &ImageResource::from_path(RED_SQUARE_IMAGE_PATH), &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let corner = mw.export_image_pixels(0, 0, 1, 1, "RGB").unwrap();
+
+    assert_eq!(vec![255, 255, 255], corner);
+}
+
+#[test]
+fn trim_uniform_border() {
+    assert_eq!((8, 6), trim_size(TRIM_WHITE_IMAGE_PATH, Trim::new(), None));
+
+    let mut trim = Trim::new();
+
+    trim.color = Some(String::from("#FFFFFF"));
+    trim.padding = 2;
+
+    assert_eq!((12, 10), trim_size(TRIM_WHITE_IMAGE_PATH, trim, None));
+
+    let mut trim = Trim::new();
+
+    trim.padding = 20;
+
+    assert_eq!((32, 24), trim_size(TRIM_WHITE_IMAGE_PATH, trim, None));
+}
+
+#[test]
+fn trim_transparent_edges() {
+    assert_eq!((8, 6), trim_size(TRIM_TRANSPARENT_IMAGE_PATH, Trim::new(), None));
+}
+
+#[test]
+fn trim_before_crop() {
+    assert_eq!(
+        (6, 6),
+        trim_size(TRIM_WHITE_IMAGE_PATH, Trim::new(), Some(Crop::Center(1f64, 1f64)))
+    );
+}
+
+#[test]
+fn resource_limits_decompression_bomb() {
+    // a PNG image which declares a huge size but has almost no data
+    let input = ImageResource::from_path(BOMB_IMAGE_PATH);
+
+    let mut output = ImageResource::with_capacity(0);
+
+    let mut config = PNGConfig::new();
+
+    config.resource_limits = Some(ResourceLimits::new());
+
+    let err = to_png(&mut output, &input, &config).unwrap_err();
+
+    assert!(is_limit_exceeded(&err));
+}
+
+#[test]
+fn orientation_of_all_inputs() {
+    // a 4x2 grayscale TIFF image whose orientation is `RightTop` (rotated 90 degrees clockwise)
+    let data = fs::read(RIGHT_TOP_IMAGE_PATH).unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.respect_orientation = true;
+
+    let mut mw = None;
+
+    identify_read(&mut mw, &ImageResource::from_path(RIGHT_TOP_IMAGE_PATH)).unwrap();
+
+    let mut outputs = Vec::new();
+
+    for input in [
+        ImageResource::from_path(RIGHT_TOP_IMAGE_PATH),
+        ImageResource::Data(data),
+        ImageResource::MagickWand(mw.unwrap()),
+    ] {
+        let mut output = ImageResource::with_capacity(0);
+
+        to_png(&mut output, &input, &config).unwrap();
+
+        outputs.push(output);
+
+        let set = DerivativeSet::new(&input, None, None).unwrap();
+
+        let mut output = ImageResource::with_capacity(0);
+
+        set.convert(&mut [(OutputConfig::PNG(&config), &mut output)]).unwrap();
+
+        outputs.push(output);
+    }
+
+    // the orientation is respected whether the image is read from a file, from data or from a wand
+    for output in outputs {
+        let id = identify_ping(&output).unwrap();
+
+        assert_eq!((2, 4), (id.resolution.width, id.resolution.height));
+    }
+}
+
+/// Get the size of the transformed 200x100 black image with a white pixel at (10, 0), and the position of its white pixel.
+fn transformed(transform: Transform) -> ((usize, usize), Option<(usize, usize)>) {
+    let mut config = PNGConfig::new();
+
+    config.transform = Some(transform);
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(WHITE_DOT_IMAGE_PATH), &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    let width = mw.get_image_width();
+    let height = mw.get_image_height();
+
+    let pixels = mw.export_image_pixels(0, 0, width, height, "I").unwrap();
+
+    let white = pixels.iter().position(|v| *v > 127).map(|i| (i % width, i / width));
+
+    ((width, height), white)
+}
+
+#[test]
+fn transform_right_angle() {
+    assert_eq!(((100, 200), Some((99, 10))), transformed(Transform::rotate(90f64)));
+    assert_eq!(((200, 100), Some((189, 99))), transformed(Transform::rotate(-180f64)));
+
+    let mut transform = Transform::new();
+
+    transform.flip = true;
+    transform.flop = true;
+
+    assert_eq!(((200, 100), Some((189, 99))), transformed(transform));
+}
+
+#[test]
+fn transform_transpose() {
+    let mut transform = Transform::new();
+
+    transform.transpose = true;
+
+    assert_eq!(((100, 200), Some((0, 10))), transformed(transform));
+
+    let mut transform = Transform::new();
+
+    transform.transverse = true;
+
+    assert_eq!(((100, 200), Some((99, 189))), transformed(transform));
+}
+
+#[test]
+fn transform_auto_crop() {
+    let ((width, height), _) = transformed(Transform::rotate(30f64));
+
+    assert!(width > 200 && height > 100);
+
+    let mut transform = Transform::rotate(30f64);
+
+    transform.auto_crop = true;
+
+    let ((width, height), _) = transformed(transform);
+
+    assert!(width < 200 && height < 100);
+}
+
+/// Get the output size of a 64x32 image after resizing it to fit 32x32.
+fn resized_size(resize_mode: ResizeMode) -> Result<(u32, u32), MagickError> {
+    let input = ImageResource::from_path(RED_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.width = 32;
+    config.height = 32;
+    config.resize_mode = resize_mode;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config)?;
+
+    let id = identify_ping(&output.into_vec().unwrap()).unwrap();
+
+    Ok((id.resolution.width, id.resolution.height))
+}
+
+#[test]
+fn resize_mode_resample() {
+    assert_eq!((32, 16), resized_size(ResizeMode::Resample).unwrap());
+}
+
+#[test]
+fn resize_mode_content_aware() {
+    assert_eq!(
+        (32, 32),
+        resized_size(ResizeMode::ContentAware {
+            fallback: true
+        })
+        .unwrap()
+    );
+
+    // without the fallback, it depends on whether ImageMagick has the liblqr delegate
+    match resized_size(ResizeMode::ContentAware {
+        fallback: false
+    }) {
+        Ok(size) => assert_eq!((32, 32), size),
+        Err(err) => assert!(format!("{err:?}").contains("liblqr")),
+    }
+}
+
+/// Draw a watermark on a 256x256 red image, and get the RGB samples of the output.
+fn watermarked_rgb(watermark: Watermark) -> Vec<u8> {
+    let mut config = PNGConfig::new();
+
+    config.watermark = Some(watermark);
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &ImageResource::from_path(RED_256_IMAGE_PATH), &config).unwrap();
+
+    let mw = output.into_magick_wand().unwrap();
+
+    mw.export_image_pixels(0, 0, 256, 256, "RGB").unwrap()
+}
+
+#[inline]
+fn rgb(pixels: &[u8], x: usize, y: usize) -> [u8; 3] {
+    let i = (y * 256 + x) * 3;
+
+    [pixels[i], pixels[i + 1], pixels[i + 2]]
+}
+
+#[test]
+fn watermark_gravity() {
+    // the gray watermark is scaled to 64x64 (a quarter of the width), and placed 16 pixels away from the edges
+    let mut watermark = Watermark::new(ImageResource::from_path(GRAY_IMAGE_PATH));
+
+    watermark.gravity = WatermarkGravity::NorthWest;
+
+    let pixels = watermarked_rgb(watermark);
+
+    assert_eq!([128, 128, 128], rgb(&pixels, 16, 16));
+    assert_eq!([128, 128, 128], rgb(&pixels, 79, 79));
+    assert_eq!([255, 0, 0], rgb(&pixels, 15, 15));
+    assert_eq!([255, 0, 0], rgb(&pixels, 80, 80));
+    assert_eq!([255, 0, 0], rgb(&pixels, 208, 208));
+
+    let mut watermark = Watermark::new(ImageResource::from_path(GRAY_IMAGE_PATH));
+
+    watermark.opacity = 0.5;
+
+    let pixels = watermarked_rgb(watermark);
+
+    // half of the gray watermark over the red image at the bottom right corner
+    let blended = rgb(&pixels, 208, 208);
+
+    assert!(blended.iter().zip([191, 64, 64]).all(|(v, expected)| v.abs_diff(expected) <= 1));
+
+    assert_eq!([255, 0, 0], rgb(&pixels, 240, 240));
+    assert_eq!([255, 0, 0], rgb(&pixels, 48, 48));
+}
+
+#[test]
+fn adjustments_identity() {
+    assert_eq!(
+        adjusted_rgb(GRADIENT_IMAGE_PATH, None).unwrap(),
+        adjusted_rgb(GRADIENT_IMAGE_PATH, Some(Adjustments::new())).unwrap()
+    );
+}
+
+#[test]
+fn adjustments_brightness_gamma() {
+    // the gray image is #808080
+    let mean = |adjustments: Adjustments| {
+        channel_means(&adjusted_rgb(GRAY_IMAGE_PATH, Some(adjustments)).unwrap())[0]
+    };
+
+    let mut adjustments = Adjustments::new();
+
+    adjustments.brightness = 20f64;
+
+    assert!(mean(adjustments) > 140f64);
+
+    adjustments.brightness = -20f64;
+
+    assert!(mean(adjustments) < 116f64);
+
+    let mut adjustments = Adjustments::new();
+
+    adjustments.gamma = 2f64;
+
+    assert!(mean(adjustments) > 140f64);
+
+    adjustments.gamma = 0.5f64;
+
+    assert!(mean(adjustments) < 116f64);
+
+    adjustments.gamma = 0f64;
+
+    assert!(adjusted_rgb(GRAY_IMAGE_PATH, Some(adjustments)).is_err());
+}
+
+#[test]
+fn adjustments_contrast() {
+    // the gradient image spans from 0 to 240, so a higher contrast spreads the samples away from the middle
+    let original = adjusted_rgb(GRADIENT_IMAGE_PATH, None).unwrap();
+
+    let mut adjustments = Adjustments::new();
+
+    adjustments.contrast = 50f64;
+
+    let adjusted = adjusted_rgb(GRADIENT_IMAGE_PATH, Some(adjustments)).unwrap();
+
+    let spread = |pixels: &[u8]| {
+        pixels.iter().map(|sample| (f64::from(*sample) - 127.5f64).abs()).sum::<f64>()
+    };
+
+    assert!(spread(&adjusted) > spread(&original));
+}
+
+#[test]
+fn adjustments_saturation_hue() {
+    let mut adjustments = Adjustments::new();
+
+    adjustments.saturation = 0f64;
+
+    let pixels = adjusted_rgb(GRADIENT_IMAGE_PATH, Some(adjustments)).unwrap();
+
+    assert!(pixels.chunks_exact(3).all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+
+    // red rotated by 180 degrees is cyan
+    let mut adjustments = Adjustments::new();
+
+    adjustments.hue = 180f64;
+
+    let pixels = adjusted_rgb(RED_IMAGE_PATH, Some(adjustments)).unwrap();
+
+    assert!(pixels.chunks_exact(3).all(|pixel| pixel[0] < 16 && pixel[1] > 239 && pixel[2] > 239));
+}
+
+#[test]
+fn adjustments_auto() {
+    // the means of the gradient image are (120, 112, 88), and they become the same after balancing the white
+    let mut adjustments = Adjustments::new();
+
+    adjustments.auto_white_balance = true;
+
+    let [r, g, b] = channel_means(&adjusted_rgb(GRADIENT_IMAGE_PATH, Some(adjustments)).unwrap());
+
+    assert!((r - g).abs() < 1f64 && (g - b).abs() < 1f64, "{r} {g} {b}");
+
+    // the samples of the gradient image are from 0 to 240, and they are stretched to the full range
+    let mut adjustments = Adjustments::new();
+
+    adjustments.auto_level = true;
+
+    let pixels = adjusted_rgb(GRADIENT_IMAGE_PATH, Some(adjustments)).unwrap();
+
+    assert_eq!(Some(&0), pixels.iter().min());
+    assert_eq!(Some(&255), pixels.iter().max());
+}
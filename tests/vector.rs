@@ -1,12 +1,29 @@
-use std::path::Path;
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(feature = "tokio")]
+use std::{sync::mpsc, thread};
 
 use image_convert::{
-    identify_ping, to_bmp, to_gif, to_gray_raw, to_ico, to_jpg, to_pgm, to_png, to_tiff, to_webp,
-    BMPConfig, ColorName, GIFConfig, GrayRawConfig, ICOConfig, ImageResource, InterlaceType,
-    JPGConfig, PGMConfig, PNGConfig, TIFFConfig, WEBPConfig,
+    convert_many, from_ico, identify_ping, identify_ping_with_policy, identify_read,
+    is_limit_exceeded, magick_rust::MagickWand, responsive_set, sniff_format, to_bmp, to_cur,
+    to_gif, to_gray_raw, to_icns, to_ico, to_ico_from_sources, to_jpg, to_pgm, to_png, to_tiff,
+    to_webp, BMPConfig, Batch, BatchConfig, BatchJob, ChromaKey, ColorName, Composition, Crop,
+    DerivativeSet, GIFConfig, GrayRawConfig, ICNSConfig, ICOConfig, ICOEncoding, ImageInput,
+    ImageLayer, ImageResource, InterlaceType, JPGConfig, Layer, LayerFit, OutputConfig, PGMConfig,
+    PNGConfig, ReadPolicy, ResizeMode, ResourceLimits, ResponsiveConfig, ResponsiveFormat,
+    TIFFConfig, TextAlign, TextLayer, Trim, WEBPConfig, Watermark,
 };
+#[cfg(feature = "tokio")]
+use image_convert::{identify_ping_async, is_cancelled, to_png_async, AsyncOutput, BlockingPool};
 
 const INPUT_IMAGE_PATH: &str = r"tests/data/dropbox.svg";
+const JPEG_IMAGE_PATH: &str = r"tests/data/landscape.jpg";
+const RED_256_IMAGE_PATH: &str = r"tests/data/red_256.png";
 
 #[test]
 fn get_identify() {
@@ -79,6 +96,127 @@ fn to_png_file2file() {
     to_png(&mut output, &input, &config).unwrap();
 }
 
+#[test]
+fn to_png_trim_crop_enlarged() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut trim = Trim::new();
+
+    trim.color = Some(String::from("#007EE5"));
+
+    // trim the blue background around the logo
+    let mut config = PNGConfig::new();
+
+    config.trim = Some(trim);
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let id = identify_ping(&output).unwrap();
+
+    let (width, height) = (id.resolution.width, id.resolution.height);
+
+    assert!(width < 512 && height < 512);
+
+    config.width = width as u16 * 2;
+    config.shrink_only = false;
+
+    let mut output = ImageResource::with_capacity(0);
+    let mut derivative = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config).unwrap();
+    convert_many(&input, &mut [(OutputConfig::PNG(&config), &mut derivative)]).unwrap();
+
+    for output in [&output, &derivative] {
+        let id = identify_ping(output).unwrap();
+
+        assert_eq!(width * 2, id.resolution.width);
+        assert!(id.resolution.height.abs_diff(height * 2) <= 1);
+    }
+
+    // keep the middle half of the icon
+    let mut config = PNGConfig::new();
+
+    config.crop = Some(Crop::Center(2f64, 1f64));
+    config.width = 1024;
+    config.shrink_only = false;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+    let mut derivative = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &input, &config).unwrap();
+    convert_many(&input, &mut [(OutputConfig::PNG(&config), &mut derivative)]).unwrap();
+
+    for output in [output, derivative] {
+        let mw = output.into_magick_wand().unwrap();
+
+        assert_eq!((1024, 512), (mw.get_image_width(), mw.get_image_height()));
+
+        // the left edge is the blue background instead of a transparent margin
+        let pixel = mw.export_image_pixels(0, 256, 1, 1, "RGBA").unwrap();
+
+        assert_eq!(255, pixel[3]);
+        assert!(pixel[2] > 200 && pixel[0] < 32);
+    }
+}
+
+#[test]
+fn to_png_chroma_key_enlarged() {
+    let data = std::fs::read(INPUT_IMAGE_PATH).unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.chroma_key = Some(ChromaKey::new("#007EE5"));
+    config.width = 1024;
+    config.shrink_only = false;
+
+    for input in [ImageResource::from_path(INPUT_IMAGE_PATH), ImageResource::Data(data)] {
+        let mut output = ImageResource::MagickWand(MagickWand::new());
+        let mut derivative = ImageResource::MagickWand(MagickWand::new());
+
+        to_png(&mut output, &input, &config).unwrap();
+        convert_many(&input, &mut [(OutputConfig::PNG(&config), &mut derivative)]).unwrap();
+
+        for output in [output, derivative] {
+            let mw = output.into_magick_wand().unwrap();
+
+            assert_eq!((1024, 1024), (mw.get_image_width(), mw.get_image_height()));
+
+            // the blue background is keyed out after the image is re-rendered
+            let pixel = mw.export_image_pixels(16, 512, 1, 1, "RGBA").unwrap();
+
+            assert_eq!(0, pixel[3]);
+        }
+    }
+}
+
+#[test]
+fn to_png_content_aware_enlarged() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.width = 1024;
+    config.height = 512;
+    config.shrink_only = false;
+    config.resize_mode = ResizeMode::ContentAware {
+        fallback: true
+    };
+
+    let mut output = ImageResource::with_capacity(0);
+    let mut derivative = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config).unwrap();
+    convert_many(&input, &mut [(OutputConfig::PNG(&config), &mut derivative)]).unwrap();
+
+    for output in [&output, &derivative] {
+        let id = identify_ping(output).unwrap();
+
+        assert_eq!((1024, 512), (id.resolution.width, id.resolution.height));
+    }
+}
+
 #[test]
 fn to_png_file2file_small() {
     let source_image_path = Path::new(INPUT_IMAGE_PATH);
@@ -170,9 +308,140 @@ fn to_ico_file2file() {
 
     let input = ImageResource::from_path(source_image_path);
 
-    let mut output = ImageResource::from_path(target_image_path);
+    let mut output = ImageResource::from_path(&target_image_path);
 
     to_ico(&mut output, &input, &config).unwrap();
+
+    // only the entries of 256 px are encoded as PNG by default
+    let icon_dir = ico::IconDir::read(fs::File::open(target_image_path).unwrap()).unwrap();
+
+    let entries: Vec<(u32, bool)> =
+        icon_dir.entries().iter().map(|entry| (entry.width(), entry.is_png())).collect();
+
+    assert_eq!(vec![(256, true), (16, false), (128, false), (64, false), (32, false)], entries);
+}
+
+#[test]
+fn to_ico_favicon_file2file() {
+    let source_image_path = Path::new(INPUT_IMAGE_PATH);
+
+    let target_image_path =
+        Path::join(source_image_path.parent().unwrap(), "dropbox_favicon_output.ico");
+
+    let mut config = ICOConfig::favicon();
+
+    config.encoding = ICOEncoding::PNG;
+
+    let input = ImageResource::from_path(source_image_path);
+
+    let mut output = ImageResource::from_path(&target_image_path);
+
+    to_ico(&mut output, &input, &config).unwrap();
+
+    let icon_dir = ico::IconDir::read(fs::File::open(target_image_path).unwrap()).unwrap();
+
+    let entries: Vec<(u32, bool)> =
+        icon_dir.entries().iter().map(|entry| (entry.width(), entry.is_png())).collect();
+
+    assert_eq!(vec![(16, true), (32, true), (48, true)], entries);
+}
+
+#[test]
+fn to_cur_file2file() {
+    let source_image_path = Path::new(INPUT_IMAGE_PATH);
+
+    let target_image_path = Path::join(source_image_path.parent().unwrap(), "dropbox_output.cur");
+
+    let mut config = ICOConfig::new();
+
+    config.size.push((32u16, 32u16));
+    config.size.push((48u16, 24u16));
+    config.hotspot = (0.5f64, 0.5f64);
+
+    let input = ImageResource::from_path(source_image_path);
+
+    let mut output = ImageResource::from_path(&target_image_path);
+
+    to_cur(&mut output, &input, &config).unwrap();
+
+    let icon_dir = ico::IconDir::read(fs::File::open(target_image_path).unwrap()).unwrap();
+
+    assert_eq!(ico::ResourceType::Cursor, icon_dir.resource_type());
+
+    // the hotspot is at the center of each entry, and the non-square entry is padded to 48x24
+    let hotspots: Vec<Option<(u16, u16)>> =
+        icon_dir.entries().iter().map(|entry| entry.cursor_hotspot()).collect();
+
+    assert_eq!(vec![Some((16, 16)), Some((24, 12))], hotspots);
+}
+
+#[test]
+fn to_ico_from_sources_data2data() {
+    // the small source is the blue icon, and the large source is red
+    let small = ImageResource::Data(std::fs::read(INPUT_IMAGE_PATH).unwrap());
+
+    let large = ImageResource::from_path(RED_256_IMAGE_PATH);
+
+    let mut config = ICOConfig::new();
+
+    config.size.push((16u16, 16u16));
+    config.size.push((48u16, 48u16));
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_ico_from_sources(&mut output, &[(&small, (32, 32)), (&large, (256, 256))], &config).unwrap();
+
+    let mut entries: Vec<(usize, [u8; 3])> = from_ico(&output)
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            let mut output = ImageResource::MagickWand(MagickWand::new());
+
+            to_png(&mut output, entry, &PNGConfig::new()).unwrap();
+
+            let mw = output.into_magick_wand().unwrap();
+
+            let size = mw.get_image_width();
+
+            assert_eq!(size, mw.get_image_height());
+
+            // a pixel on the left edge, which is inside the rounded square of the icon
+            let pixel = mw
+                .export_image_pixels((size / 16) as isize, (size / 2) as isize, 1, 1, "RGB")
+                .unwrap();
+
+            (size, [pixel[0], pixel[1], pixel[2]])
+        })
+        .collect();
+
+    entries.sort_unstable_by_key(|(size, _)| *size);
+
+    let sizes: Vec<usize> = entries.iter().map(|(size, _)| *size).collect();
+
+    assert_eq!(vec![16, 32, 48, 256], sizes);
+
+    for (size, [r, _, b]) in entries {
+        if size == 32 {
+            assert!(b > 200 && r < 32, "{size}: the entry is not from the small source");
+        } else {
+            assert!(r > 200 && b < 32, "{size}: the entry is not from the large source");
+        }
+    }
+}
+
+#[test]
+fn to_icns_file2file() {
+    let source_image_path = Path::new(INPUT_IMAGE_PATH);
+
+    let target_image_path = Path::join(source_image_path.parent().unwrap(), "dropbox_output.icns");
+
+    let config = ICNSConfig::new();
+
+    let input = ImageResource::from_path(source_image_path);
+
+    let mut output = ImageResource::from_path(target_image_path);
+
+    to_icns(&mut output, &input, &config).unwrap();
 }
 
 #[test]
@@ -211,3 +480,733 @@ fn to_pgm_file2file() {
 
     to_pgm(&mut output, &input, &config).unwrap();
 }
+
+#[test]
+fn read_policy_sniff() {
+    assert_eq!(Some("JPEG"), sniff_format(&[0xFF, 0xD8, 0xFF, 0xE0]));
+    assert_eq!(Some("PNG"), sniff_format(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"));
+    assert_eq!(Some("GIF"), sniff_format(b"GIF89a"));
+    assert_eq!(Some("WEBP"), sniff_format(b"RIFF\0\0\0\0WEBPVP8 "));
+    assert_eq!(Some("PS"), sniff_format(b"%!PS-Adobe-3.0"));
+    assert_eq!(Some("MVG"), sniff_format(b"push graphic-context\nviewbox 0 0 1 1"));
+    assert_eq!(Some("MSL"), sniff_format(b"<?xml version=\"1.0\"?><image><read /></image>"));
+    assert_eq!(Some("SVG"), sniff_format(&fs::read(INPUT_IMAGE_PATH).unwrap()));
+    assert_eq!(None, sniff_format(b"plain text"));
+}
+
+#[test]
+fn read_policy_allowed_formats() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut policy = ReadPolicy::new();
+
+    policy.forbid_delegates = false;
+
+    assert!(identify_ping_with_policy(&input, &policy).is_ok());
+
+    policy.allowed_formats.push(String::from("PNG"));
+
+    assert!(identify_ping_with_policy(&input, &policy).is_err());
+
+    let input = ImageResource::Data(fs::read(INPUT_IMAGE_PATH).unwrap());
+
+    assert!(identify_ping_with_policy(&input, &policy).is_err());
+}
+
+#[test]
+fn read_policy_forbid_delegates() {
+    let input = ImageResource::Data(
+        b"push graphic-context\nviewbox 0 0 1 1\npop graphic-context\n".to_vec(),
+    );
+
+    let mut output = ImageResource::with_capacity(0);
+
+    let mut config = PNGConfig::new();
+
+    config.read_policy = Some(ReadPolicy::new());
+
+    assert!(to_png(&mut output, &input, &config).is_err());
+
+    // SVG images can reference other resources
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    assert!(to_png(&mut output, &input, &config).is_err());
+
+    let input = ImageResource::Data(fs::read(INPUT_IMAGE_PATH).unwrap());
+
+    assert!(to_png(&mut output, &input, &config).is_err());
+}
+
+#[test]
+fn read_policy_forbid_path_indirection() {
+    let policy = ReadPolicy::new();
+
+    for path in [
+        format!("svg:{INPUT_IMAGE_PATH}"),
+        format!("@{INPUT_IMAGE_PATH}"),
+        format!("{INPUT_IMAGE_PATH}[0]"),
+    ] {
+        let input = ImageResource::from_path(path);
+
+        assert!(identify_ping_with_policy(&input, &policy).is_err());
+    }
+}
+
+#[test]
+fn read_policy_allow_path_indirection() {
+    let mut policy = ReadPolicy::new();
+
+    policy.forbid_delegates = false;
+    policy.forbid_path_indirection = false;
+
+    for path in [format!("svg:{INPUT_IMAGE_PATH}"), format!("{INPUT_IMAGE_PATH}[0]")] {
+        let input = ImageResource::from_path(path);
+
+        assert_eq!(512, identify_ping_with_policy(&input, &policy).unwrap().resolution.width);
+    }
+}
+
+#[test]
+fn read_policy_unrecognized_format() {
+    // the format is rejected before ImageMagick touches the data
+    let input = ImageResource::Data(b"plain text".to_vec());
+
+    let err = identify_ping_with_policy(&input, &ReadPolicy::new()).unwrap_err();
+
+    assert_eq!("The input format cannot be recognized.", err.to_string());
+
+    let mut policy = ReadPolicy::new();
+
+    policy.forbid_delegates = false;
+
+    assert!(identify_ping_with_policy(&input, &policy).is_err());
+}
+
+#[test]
+fn read_policy_colon_in_file_name() {
+    // `2024-01-01T10` is not an ImageMagick coder, so it is not a `format:` prefix
+    let path = "2024-01-01T10:00.jpg";
+
+    fs::copy(JPEG_IMAGE_PATH, path).unwrap();
+
+    let result = identify_ping_with_policy(&ImageResource::from_path(path), &ReadPolicy::web());
+
+    fs::remove_file(path).unwrap();
+
+    assert_eq!("JPEG", result.unwrap().format);
+}
+
+#[test]
+fn resource_limits_max_width() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut output = ImageResource::with_capacity(0);
+
+    let mut config = PNGConfig::new();
+
+    let mut limits = ResourceLimits::new();
+
+    limits.max_width = 256;
+
+    config.resource_limits = Some(limits);
+
+    let err = to_png(&mut output, &input, &config).unwrap_err();
+
+    assert!(is_limit_exceeded(&err));
+
+    limits.max_width = 512;
+
+    config.resource_limits = Some(limits);
+
+    to_png(&mut output, &input, &config).unwrap();
+}
+
+#[test]
+fn resource_limits_timeout() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    let mut limits = ResourceLimits::new();
+
+    limits.timeout = Some(Duration::ZERO);
+
+    config.resource_limits = Some(limits);
+
+    let err = to_png(&mut ImageResource::with_capacity(0), &input, &config).unwrap_err();
+
+    assert!(is_limit_exceeded(&err));
+
+    limits.timeout = Some(Duration::from_secs(60));
+
+    config.resource_limits = Some(limits);
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    // the deadline is not left in the output image
+    let mw = output.into_magick_wand().unwrap();
+
+    assert!(mw.get_image_artifact("image-convert:deadline").is_err());
+}
+
+#[test]
+fn derivative_set_reads_input_once() {
+    let mut config = PNGConfig::new();
+
+    config.width = 1024;
+    config.height = 1024;
+    config.shrink_only = false;
+
+    let mut raster = ImageResource::with_capacity(0);
+
+    to_png(&mut raster, &ImageResource::from_path(INPUT_IMAGE_PATH), &config).unwrap();
+
+    let source_image_path = env::temp_dir().join("image-convert-derivatives.png");
+
+    fs::write(&source_image_path, raster.into_vec().unwrap()).unwrap();
+
+    let input = ImageResource::from_path(&source_image_path);
+
+    let set = DerivativeSet::new(&input, None, None).unwrap();
+
+    // the input image cannot be read again after the set is created
+    fs::remove_file(&source_image_path).unwrap();
+
+    let mut thumbnail_config = PNGConfig::new();
+    thumbnail_config.width = 128;
+
+    let mut medium_config = JPGConfig::new();
+    medium_config.width = 640;
+
+    let mut webp_config = WEBPConfig::new();
+    webp_config.width = 800;
+
+    let mut thumbnail = ImageResource::with_capacity(0);
+    let mut medium = ImageResource::with_capacity(0);
+    let mut webp = ImageResource::with_capacity(0);
+
+    set.convert(&mut [
+        (OutputConfig::PNG(&thumbnail_config), &mut thumbnail),
+        (OutputConfig::JPG(&medium_config), &mut medium),
+        (OutputConfig::WEBP(&webp_config), &mut webp),
+    ])
+    .unwrap();
+
+    for (output, width, format) in
+        [(&thumbnail, 128, "PNG"), (&medium, 640, "JPEG"), (&webp, 800, "WEBP")]
+    {
+        let id = identify_ping(output).unwrap();
+
+        assert_eq!(width, id.resolution.width);
+        assert_eq!(width, id.resolution.height);
+        assert_eq!(format, id.format);
+    }
+}
+
+#[test]
+fn convert_many_vector() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut small_config = PNGConfig::new();
+    small_config.width = 64;
+
+    let mut large_config = PNGConfig::new();
+    large_config.width = 1024;
+    large_config.shrink_only = false;
+
+    let mut small = ImageResource::with_capacity(0);
+    let mut large = ImageResource::with_capacity(0);
+
+    convert_many(&input, &mut [
+        (OutputConfig::PNG(&small_config), &mut small),
+        (OutputConfig::PNG(&large_config), &mut large),
+    ])
+    .unwrap();
+
+    assert_eq!(64, identify_ping(&small).unwrap().resolution.width);
+    assert_eq!(1024, identify_ping(&large).unwrap().resolution.width);
+}
+
+#[test]
+fn responsive_set_shrink_only() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let config = ResponsiveConfig::new();
+
+    let set = responsive_set(&input, &config).unwrap();
+
+    // the input image is 512 pixels wide, so only 320 is generated
+    assert_eq!(2, set.images.len());
+
+    for image in set.images.iter() {
+        assert_eq!(320, image.width);
+        assert_eq!(320, image.height);
+        assert!(image.byte_size() > 0);
+
+        let id = identify_ping(&ImageResource::Data(image.data.clone())).unwrap();
+
+        assert_eq!(u32::from(image.width), id.resolution.width);
+    }
+
+    let html = set.to_html("Dropbox", "100vw", |image| {
+        format!("/dropbox-{}.{}", image.width, image.format.extension())
+    });
+
+    assert_eq!(
+        "<picture>\n  <source type=\"image/webp\" srcset=\"/dropbox-320.webp 320w\" \
+         sizes=\"100vw\">\n  <img src=\"/dropbox-320.jpg\" srcset=\"/dropbox-320.jpg 320w\" \
+         sizes=\"100vw\" width=\"320\" height=\"320\" alt=\"Dropbox\">\n</picture>",
+        html
+    );
+}
+
+#[test]
+fn responsive_set_enlarge() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = ResponsiveConfig::new();
+
+    config.widths = vec![320, 640];
+    config.formats = vec![ResponsiveFormat::PNG];
+    config.shrink_only = false;
+
+    let set = responsive_set(&input, &config).unwrap();
+
+    let widths: Vec<u16> = set.images.iter().map(|image| image.width).collect();
+
+    assert_eq!(vec![320, 640, 1280], widths);
+}
+
+#[test]
+fn responsive_set_smaller_than_every_width() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = ResponsiveConfig::new();
+
+    config.widths = vec![1024, 2048];
+    config.formats = vec![ResponsiveFormat::PNG];
+
+    let set = responsive_set(&input, &config).unwrap();
+
+    // the input image is 512 pixels wide
+    assert_eq!(1, set.images.len());
+    assert_eq!(512, set.images[0].width);
+    assert_eq!(512, set.images[0].height);
+}
+
+/// A writer which only publishes the written data when it is flushed.
+#[derive(Default)]
+struct FlushedBuffer {
+    pending: Vec<u8>,
+    flushed: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for FlushedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushed.lock().unwrap().append(&mut self.pending);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn to_png_writer() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.width = 64;
+
+    let buffer = FlushedBuffer::default();
+    let flushed = buffer.flushed.clone();
+
+    let mut output = ImageResource::from_writer(buffer);
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let mut data = ImageResource::with_capacity(0);
+
+    to_png(&mut data, &input, &config).unwrap();
+
+    assert_eq!(data.into_vec().unwrap(), *flushed.lock().unwrap());
+}
+
+#[test]
+fn to_ico_writer() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = ICOConfig::new();
+
+    config.size = vec![(16, 16), (32, 32), (48, 48)];
+
+    let buffer = FlushedBuffer::default();
+    let flushed = buffer.flushed.clone();
+
+    let mut output = ImageResource::from_writer(buffer);
+
+    to_ico(&mut output, &input, &config).unwrap();
+
+    let mut data = ImageResource::with_capacity(0);
+
+    to_ico(&mut data, &input, &config).unwrap();
+
+    let data = data.into_vec().unwrap();
+
+    assert_eq!(data, *flushed.lock().unwrap());
+
+    let mut sizes: Vec<(u32, u32)> = from_ico(&data)
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            let id = identify_ping(entry).unwrap();
+
+            (id.resolution.width, id.resolution.height)
+        })
+        .collect();
+
+    sizes.sort_unstable();
+
+    assert_eq!(vec![(16, 16), (32, 32), (48, 48)], sizes);
+}
+
+#[test]
+fn writer_as_input() {
+    let input = ImageResource::from_writer(io::sink());
+
+    let mut output = ImageResource::with_capacity(0);
+
+    assert!(to_png(&mut output, &input, &PNGConfig::new()).is_err());
+}
+
+#[test]
+fn identify_slice() {
+    let data = fs::read(INPUT_IMAGE_PATH).unwrap();
+
+    let id = identify_ping(&data[..]).unwrap();
+
+    assert_eq!(512, id.resolution.width);
+    assert_eq!(512, id.resolution.height);
+
+    let mut output = None;
+
+    let id = identify_read(&mut output, &ImageInput::Data(&data)).unwrap();
+
+    assert_eq!(512, id.resolution.width);
+    assert!(output.is_some());
+}
+
+#[test]
+fn to_png_slice() {
+    let data = fs::read(INPUT_IMAGE_PATH).unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.width = 32;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &data[..], &config).unwrap();
+
+    let id = identify_ping(&output).unwrap();
+
+    assert_eq!(32, id.resolution.width);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn to_png_mmap() {
+    use image_convert::MappedImage;
+
+    // SAFETY: the test fixture is never modified
+    let input = unsafe { MappedImage::open(INPUT_IMAGE_PATH) }.unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.width = 32;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &config).unwrap();
+
+    let id = identify_ping(&output).unwrap();
+
+    assert_eq!(32, id.resolution.width);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn to_png_async_data() {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = PNGConfig::new();
+
+    config.width = 64;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png_async(&mut output, input, config).await.unwrap();
+
+    let id = identify_ping_async(output).await.unwrap();
+
+    assert_eq!(64, id.resolution.width);
+    assert_eq!("PNG", id.format);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn to_png_async_writer() {
+    let data =
+        ImageResource::from_async_reader(tokio::fs::File::open(INPUT_IMAGE_PATH).await.unwrap())
+            .await
+            .unwrap();
+
+    let mut config = PNGConfig::new();
+
+    config.width = 64;
+
+    let mut output: Vec<u8> = Vec::new();
+
+    to_png_async(AsyncOutput::writer(&mut output), data, config).await.unwrap();
+
+    let id = identify_ping(&output).unwrap();
+
+    assert_eq!(64, id.resolution.width);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn blocking_pool_cancellation() {
+    let pool = BlockingPool::new(1);
+
+    let (tx, rx) = mpsc::channel();
+
+    let result = tokio::time::timeout(
+        Duration::from_millis(50),
+        pool.run(move |cancellation| {
+            thread::sleep(Duration::from_millis(300));
+
+            tx.send(cancellation.is_cancelled()).unwrap();
+        }),
+    )
+    .await;
+
+    assert!(result.is_err());
+    assert!(rx.recv().unwrap());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn blocking_pool_cancellation_of_conversion() {
+    let pool = BlockingPool::new(1);
+
+    let (tx, rx) = mpsc::channel();
+
+    let result = tokio::time::timeout(
+        Duration::from_millis(50),
+        pool.run(move |_| {
+            thread::sleep(Duration::from_millis(300));
+
+            let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+            let mut output = ImageResource::with_capacity(0);
+
+            // the conversion functions check the cancellation of the current job by themselves
+            let mut config = ICOConfig::new();
+
+            config.size.push((16, 16));
+
+            tx.send(to_ico(&mut output, &input, &config)).unwrap();
+        }),
+    )
+    .await;
+
+    assert!(result.is_err());
+    assert!(is_cancelled(&rx.recv().unwrap().unwrap_err()));
+}
+
+fn batch_config() -> BatchConfig {
+    let mut config = PNGConfig::new();
+
+    config.width = 48;
+
+    BatchConfig::PNG(config)
+}
+
+#[test]
+fn batch_collects_results() {
+    let data = fs::read(INPUT_IMAGE_PATH).unwrap();
+
+    let jobs = vec![
+        BatchJob::new(
+            ImageResource::from_path(INPUT_IMAGE_PATH),
+            ImageResource::with_capacity(0),
+            batch_config(),
+        ),
+        BatchJob::new(
+            ImageResource::Data(b"not an image".to_vec()),
+            ImageResource::with_capacity(0),
+            batch_config(),
+        ),
+        BatchJob::new(ImageResource::Data(data), ImageResource::with_capacity(0), batch_config()),
+    ];
+
+    let mut batch = Batch::new();
+
+    batch.workers = 2;
+    batch.magick_threads = 1;
+
+    let report = batch.run(jobs).unwrap();
+
+    assert_eq!(3, report.results.len());
+    assert_eq!(2, report.stats.succeeded);
+    assert_eq!(1, report.stats.failed);
+
+    assert!(report.results[1].result.is_err());
+
+    for i in [0, 2] {
+        let result = &report.results[i];
+
+        assert!(result.result.is_ok());
+
+        let id = identify_ping(&result.output).unwrap();
+
+        assert_eq!(48, id.resolution.width);
+    }
+}
+
+fn convert_watermarked(watermark: Option<Watermark>) -> Vec<u8> {
+    let input = ImageResource::from_path(INPUT_IMAGE_PATH);
+
+    let mut config = JPGConfig::new();
+
+    config.width = 256;
+    config.watermark = watermark;
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_jpg(&mut output, &input, &config).unwrap();
+
+    output.into_vec().unwrap()
+}
+
+#[test]
+fn watermark_tiled() {
+    let mut logo = ImageResource::with_capacity(0);
+
+    let mut logo_config = PNGConfig::new();
+
+    logo_config.width = 100;
+
+    to_png(&mut logo, &ImageResource::from_path(INPUT_IMAGE_PATH), &logo_config).unwrap();
+
+    let mut watermark = Watermark::new(logo);
+
+    watermark.scale = 0.1;
+    watermark.tiled = true;
+
+    let watermarked = convert_watermarked(Some(watermark));
+
+    assert_ne!(convert_watermarked(None), watermarked);
+}
+
+#[test]
+fn composition_social_card() {
+    let mut composition = Composition::new(1200, 630);
+
+    composition.background = String::from("#1E1E1E");
+
+    let mut background = ImageLayer::new(ImageResource::from_path(INPUT_IMAGE_PATH));
+
+    background.fit = LayerFit::Cover;
+
+    let mut logo = ImageLayer::new(ImageResource::from_path(INPUT_IMAGE_PATH));
+
+    logo.x = 40;
+    logo.y = 40;
+    logo.width = 96;
+    logo.height = 64;
+    logo.fit = LayerFit::Contain;
+
+    let mut title =
+        TextLayer::new("A title which is long enough to be wrapped into more lines than allowed");
+
+    title.color = String::from("white");
+    title.size = 64f64;
+    title.x = 40;
+    title.y = 200;
+    title.width = 1120;
+    title.align = TextAlign::Center;
+    title.max_lines = 2;
+
+    composition.layers.push(Layer::Image(background));
+    composition.layers.push(Layer::Image(logo));
+    composition.layers.push(Layer::Text(title));
+
+    let mw = composition.render().unwrap();
+
+    let input = ImageResource::MagickWand(mw);
+
+    let mut output = ImageResource::with_capacity(0);
+
+    to_png(&mut output, &input, &PNGConfig::new()).unwrap();
+
+    let id = identify_ping(&output).unwrap();
+
+    assert_eq!(1200, id.resolution.width);
+    assert_eq!(630, id.resolution.height);
+}
+
+/// Render black text on a white 400x400 canvas, and get the height of the rows which have any text pixel.
+fn composition_text_height(text: &str, max_lines: usize) -> usize {
+    let mut composition = Composition::new(400, 400);
+
+    let mut layer = TextLayer::new(text);
+
+    layer.color = String::from("black");
+    layer.size = 24f64;
+    layer.width = 400;
+    layer.max_lines = max_lines;
+
+    composition.layers.push(Layer::Text(layer));
+
+    let mw = composition.render().unwrap();
+
+    let red = mw.export_image_pixels(0, 0, 400, 400, "R").unwrap();
+
+    let rows: Vec<usize> = red
+        .chunks_exact(400)
+        .enumerate()
+        .filter(|(_, row)| row.iter().any(|sample| *sample < 128))
+        .map(|(y, _)| y)
+        .collect();
+
+    rows.last().unwrap() - rows[0] + 1
+}
+
+#[test]
+fn composition_max_lines() {
+    let text = "A text which is long enough to be wrapped into more lines than allowed, because \
+                it goes on and on with more words than a line can hold";
+
+    let line_height = composition_text_height("Ag", 0);
+
+    // the text is wrapped into at least three lines without a limit
+    assert!(composition_text_height(text, 0) > line_height * 5 / 2);
+
+    // the text is cut to two lines
+    let height = composition_text_height(text, 2);
+
+    assert!(height > line_height * 3 / 2);
+    assert!(height < line_height * 5 / 2);
+}
+
+#[test]
+fn composition_incorrect_size() {
+    assert!(Composition::new(0, 630).render().is_err());
+}